@@ -1,18 +1,127 @@
-use hex;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt;
+use core::ops::Deref;
+use ripemd::Ripemd160;
 use serde::de::{Deserializer, Error as DeError, Visitor};
 use serde::{Deserialize, Serialize};
-use std::fmt;
-use std::ops::Deref;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "std")]
+use std::io::{Cursor, Read, Write};
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+// RIPEMD160(SHA256(data)), as committed to by P2PKH/P2SH/P2WPKH scripts
+fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(data)).into()
+}
+
+// SHA256(SHA256(data)), used for txids and base58check checksums
+fn sha256d(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+// Byte destination for the encoders, so `to_bytes` works without `std::io`
+trait Sink {
+    type Error;
+    fn put(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+impl Sink for Vec<u8> {
+    type Error = Infallible;
+    fn put(&mut self, bytes: &[u8]) -> Result<(), Infallible> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+// Counts bytes instead of storing them, for sizes without an allocation
+struct LenCounter(usize);
+
+impl Sink for LenCounter {
+    type Error = Infallible;
+    fn put(&mut self, bytes: &[u8]) -> Result<(), Infallible> {
+        self.0 += bytes.len();
+        Ok(())
+    }
+}
+
+impl Sink for Sha256 {
+    type Error = Infallible;
+    fn put(&mut self, bytes: &[u8]) -> Result<(), Infallible> {
+        self.update(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+struct IoSink<'a, W: Write>(&'a mut W);
+
+#[cfg(feature = "std")]
+impl<W: Write> Sink for IoSink<'_, W> {
+    type Error = std::io::Error;
+    fn put(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.0.write_all(bytes)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
 pub struct CompactSize {
     pub value: u64,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum BitcoinError {
-    InsufficientBytes,
+    // The field starting at `offset` needs `needed` bytes but the buffer ends first.
+    // Offsets are relative to the start of the buffer given to the outermost decoder.
+    InsufficientBytes { offset: usize, needed: usize },
     InvalidFormat,
+    // A script's length prefix decoded fine but claims more bytes than the buffer holds;
+    // `offset` is the script's length prefix and `needed` covers prefix plus body
+    TruncatedScript { offset: usize, needed: usize },
+}
+
+impl fmt::Display for BitcoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitcoinError::InsufficientBytes { offset, needed } => write!(
+                f,
+                "not enough bytes to decode: {} needed at offset {}",
+                needed, offset
+            ),
+            BitcoinError::InvalidFormat => write!(f, "malformed encoding"),
+            BitcoinError::TruncatedScript { offset, needed } => write!(
+                f,
+                "script length exceeds the remaining bytes: {} needed at offset {}",
+                needed, offset
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BitcoinError {}
+
+impl BitcoinError {
+    // Rebase the offset reported by a nested decoder onto the enclosing buffer
+    fn shifted(self, base: usize) -> Self {
+        match self {
+            BitcoinError::InsufficientBytes { offset, needed } => BitcoinError::InsufficientBytes {
+                offset: base + offset,
+                needed,
+            },
+            BitcoinError::TruncatedScript { offset, needed } => BitcoinError::TruncatedScript {
+                offset: base + offset,
+                needed,
+            },
+            other => other,
+        }
+    }
 }
 
 impl CompactSize {
@@ -23,29 +132,41 @@ impl CompactSize {
 
     // Convert the CompactSize to Bitcoin's variable-length encoding
     pub fn to_bytes(&self) -> Vec<u8> {
-        let value = self.value;
         let mut encoded = Vec::new();
+        let Ok(()) = self.encode(&mut encoded);
+        encoded
+    }
+
+    // Write the variable-length encoding straight to a stream
+    #[cfg(feature = "std")]
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.encode(&mut IoSink(writer))
+    }
+
+    fn encode<S: Sink>(&self, out: &mut S) -> Result<(), S::Error> {
+        let value = self.value;
 
         if value < 0xFD {
-            encoded.push(value as u8);
+            out.put(&[value as u8])
         } else if value <= 0xFFFF {
-            encoded.push(0xFD);
-            encoded.extend_from_slice(&(value as u16).to_le_bytes());
+            out.put(&[0xFD])?;
+            out.put(&(value as u16).to_le_bytes())
         } else if value <= 0xFFFF_FFFF {
-            encoded.push(0xFE);
-            encoded.extend_from_slice(&(value as u32).to_le_bytes());
+            out.put(&[0xFE])?;
+            out.put(&(value as u32).to_le_bytes())
         } else {
-            encoded.push(0xFF);
-            encoded.extend_from_slice(&value.to_le_bytes());
+            out.put(&[0xFF])?;
+            out.put(&value.to_le_bytes())
         }
-
-        encoded
     }
 
     // Decode a CompactSize from a byte slice, returning the value and bytes consumed
     pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
         if bytes.is_empty() {
-            return Err(BitcoinError::InsufficientBytes);
+            return Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 1,
+            });
         }
 
         let first = bytes[0];
@@ -55,7 +176,10 @@ impl CompactSize {
 
             0xFD => {
                 if bytes.len() < 3 {
-                    return Err(BitcoinError::InsufficientBytes);
+                    return Err(BitcoinError::InsufficientBytes {
+                        offset: 0,
+                        needed: 3,
+                    });
                 }
                 let val = u16::from_le_bytes([bytes[1], bytes[2]]) as u64;
                 Ok((CompactSize::new(val), 3))
@@ -63,7 +187,10 @@ impl CompactSize {
 
             0xFE => {
                 if bytes.len() < 5 {
-                    return Err(BitcoinError::InsufficientBytes);
+                    return Err(BitcoinError::InsufficientBytes {
+                        offset: 0,
+                        needed: 5,
+                    });
                 }
                 let val = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as u64;
                 Ok((CompactSize::new(val), 5))
@@ -71,7 +198,10 @@ impl CompactSize {
 
             0xFF => {
                 if bytes.len() < 9 {
-                    return Err(BitcoinError::InsufficientBytes);
+                    return Err(BitcoinError::InsufficientBytes {
+                        offset: 0,
+                        needed: 9,
+                    });
                 }
                 let val = u64::from_le_bytes([
                     bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7], bytes[8],
@@ -80,10 +210,152 @@ impl CompactSize {
             }
         }
     }
+
+    // Read a CompactSize from a stream, consuming only the bytes that belong to it
+    #[cfg(feature = "std")]
+    pub fn read_from<R: Read>(r: &mut R) -> Result<CompactSize, BitcoinError> {
+        let mut buf = [0u8; 9];
+        let len =
+            CompactSize::read_encoding(r, &mut buf).map_err(|(e, needed)| match e.kind() {
+                std::io::ErrorKind::UnexpectedEof => {
+                    BitcoinError::InsufficientBytes { offset: 0, needed }
+                }
+                _ => BitcoinError::InvalidFormat,
+            })?;
+
+        let (cs, _) = CompactSize::from_bytes(&buf[..len])?;
+        Ok(cs)
+    }
+
+    // Same as `read_from` but keeps the io error, so truncation is `UnexpectedEof`
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read>(reader: &mut R) -> std::io::Result<CompactSize> {
+        let mut buf = [0u8; 9];
+        let len = CompactSize::read_encoding(reader, &mut buf).map_err(|(e, _)| e)?;
+
+        let (cs, _) = CompactSize::from_bytes(&buf[..len]).expect("buffer holds a full encoding");
+        Ok(cs)
+    }
+
+    // Read the prefix byte and the bytes it announces into `buf`, returning the
+    // encoding's length; a failed read also reports the full width being read
+    #[cfg(feature = "std")]
+    fn read_encoding<R: Read>(
+        r: &mut R,
+        buf: &mut [u8; 9],
+    ) -> Result<usize, (std::io::Error, usize)> {
+        r.read_exact(&mut buf[..1]).map_err(|e| (e, 1))?;
+
+        let len = match buf[0] {
+            0xFD => 3,
+            0xFE => 5,
+            0xFF => 9,
+            _ => 1,
+        };
+        r.read_exact(&mut buf[1..len]).map_err(|e| (e, len))?;
+        Ok(len)
+    }
+
+    // Number of bytes `to_bytes` produces for this value
+    pub fn serialized_len(&self) -> usize {
+        match self.value {
+            0..=0xFC => 1,
+            0xFD..=0xFFFF => 3,
+            0x1_0000..=0xFFFF_FFFF => 5,
+            _ => 9,
+        }
+    }
 }
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// A transaction id. `.0` holds the internal (wire) byte order; `Serialize` and
+/// `Display` emit the byte-reversed hex that block explorers display.
+/// Ordering compares the internal byte array.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
 pub struct Txid(pub [u8; 32]);
 
+impl Txid {
+    /// Internal (wire-order) bytes, without the display reversal.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    /// Parses the 64-character display hex, storing it reversed like `Deserialize`.
+    pub fn from_hex(s: &str) -> Result<Txid, BitcoinError> {
+        let bytes = hex::decode(s).map_err(|_| BitcoinError::InvalidFormat)?;
+        if bytes.len() != 32 {
+            return Err(BitcoinError::InvalidFormat);
+        }
+
+        let mut reversed = [0u8; 32];
+        for (dst, src) in reversed.iter_mut().zip(bytes.iter().rev()) {
+            *dst = *src;
+        }
+        Ok(Txid(reversed))
+    }
+}
+
+impl fmt::Display for Txid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter().rev() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(13) ^ v[0];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(16) ^ v[2];
+    v[0] = v[0].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(21) ^ v[0];
+    v[2] = v[2].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(17) ^ v[2];
+    v[2] = v[2].rotate_left(32);
+}
+
+// SipHash-2-4 keyed with (k0, k1)
+fn siphash24(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut v = [
+        k0 ^ 0x736f_6d65_7073_6575,
+        k1 ^ 0x646f_7261_6e64_6f6d,
+        k0 ^ 0x6c79_6765_6e65_7261,
+        k1 ^ 0x7465_6462_7974_6573,
+    ];
+
+    let mut compress = |m: u64| {
+        v[3] ^= m;
+        sip_round(&mut v);
+        sip_round(&mut v);
+        v[0] ^= m;
+    };
+
+    let chunks = data.chunks_exact(8);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        compress(u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+
+    // Final block: remaining bytes with the message length in the top byte
+    let mut last = [0u8; 8];
+    last[..tail.len()].copy_from_slice(tail);
+    last[7] = data.len() as u8;
+    compress(u64::from_le_bytes(last));
+
+    v[2] ^= 0xFF;
+    for _ in 0..4 {
+        sip_round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+/// BIP 152 short transaction id: SipHash-2-4 of the (internal order) txid keyed
+/// with `k0`/`k1`, truncated to the low 6 bytes.
+pub fn short_txid(txid: &Txid, k0: u64, k1: u64) -> u64 {
+    siphash24(k0, k1, &txid.0) & 0xFFFF_FFFF_FFFF
+}
+
 impl Serialize for Txid {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -135,7 +407,8 @@ impl<'de> Deserialize<'de> for Txid {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+// Ordered by txid, then vout
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct OutPoint {
     pub txid: Txid,
     pub vout: u32,
@@ -151,21 +424,31 @@ impl OutPoint {
 
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::with_capacity(36);
-        let mut txid_bytes = self.txid.0;
-        txid_bytes.reverse(); // Serialize txid in little-endian order
-        bytes.extend_from_slice(&txid_bytes);
-        bytes.extend_from_slice(&self.vout.to_le_bytes());
+        let Ok(()) = self.encode(&mut bytes);
         bytes
     }
 
+    #[cfg(feature = "std")]
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.encode(&mut IoSink(writer))
+    }
+
+    fn encode<S: Sink>(&self, out: &mut S) -> Result<(), S::Error> {
+        // Txid is already held in internal (wire) order
+        out.put(&self.txid.0)?;
+        out.put(&self.vout.to_le_bytes())
+    }
+
     pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
         if bytes.len() < 36 {
-            return Err(BitcoinError::InsufficientBytes);
+            return Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 36,
+            });
         }
 
         let mut txid_bytes = [0u8; 32];
         txid_bytes.copy_from_slice(&bytes[..32]);
-        txid_bytes.reverse(); // txid is stored in little-endian, reverse to internal format
 
         let mut vout_bytes = [0u8; 4];
         vout_bytes.copy_from_slice(&bytes[32..36]);
@@ -173,8 +456,234 @@ impl OutPoint {
 
         Ok((OutPoint::new(txid_bytes, vout), 36))
     }
+
+    // The null outpoint (all-zero txid, vout 0xFFFFFFFF) spent by coinbase inputs
+    fn is_null(&self) -> bool {
+        self.txid.0 == [0u8; 32] && self.vout == 0xFFFFFFFF
+    }
+}
+
+/// Network parameters used when encoding or decoding addresses.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    pub fn bech32_hrp(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "bc",
+            Network::Testnet | Network::Signet => "tb",
+            Network::Regtest => "bcrt",
+        }
+    }
+
+    pub fn p2pkh_version(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            Network::Testnet | Network::Signet | Network::Regtest => 0x6F,
+        }
+    }
+
+    pub fn p2sh_version(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x05,
+            Network::Testnet | Network::Signet | Network::Regtest => 0xC4,
+        }
+    }
+}
+
+// secp256k1 curve order divided by two; BIP 62 requires S <= this
+const SECP256K1_HALF_ORDER: [u8; 32] = [
+    0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x5D, 0x57, 0x6E, 0x73, 0x57, 0xA4, 0x50, 0x1D, 0xDF, 0xE9, 0x2F, 0x46, 0x68, 0x1B, 0x20, 0xA0,
+];
+
+// S value of a DER signature followed by a sighash byte, without leading zero bytes
+fn der_signature_s(sig: &[u8]) -> Option<&[u8]> {
+    signature_sighash_type(sig)?;
+    let der = &sig[..sig.len() - 1];
+    if der.len() < 8 || der[2] != 0x02 {
+        return None;
+    }
+    let r_len = der[3] as usize;
+    let s_tag = 4 + r_len;
+    if der.len() < s_tag + 2
+        || der[s_tag] != 0x02
+        || der.len() != s_tag + 2 + der[s_tag + 1] as usize
+    {
+        return None;
+    }
+    let s_value = &der[s_tag + 2..];
+    let first_nonzero = s_value
+        .iter()
+        .position(|&b| b != 0)
+        .unwrap_or(s_value.len());
+    Some(&s_value[first_nonzero..])
+}
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GEN: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk: u32 = 1;
+    for &v in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ff_ffff) << 5) ^ v as u32;
+        for (i, g) in GEN.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+// Decodes a segwit address into (witness version, program), checking the BIP173/BIP350 checksum
+fn decode_segwit_address(address: &str, hrp: &str) -> Result<(u8, Vec<u8>), BitcoinError> {
+    let has_lower = address.bytes().any(|c| c.is_ascii_lowercase());
+    let has_upper = address.bytes().any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper {
+        return Err(BitcoinError::InvalidFormat);
+    }
+    let address = address.to_ascii_lowercase();
+
+    let sep = address.rfind('1').ok_or(BitcoinError::InvalidFormat)?;
+    let (addr_hrp, data_part) = (&address[..sep], &address[sep + 1..]);
+    if addr_hrp != hrp || data_part.len() < 7 {
+        return Err(BitcoinError::InvalidFormat);
+    }
+
+    let data = data_part
+        .bytes()
+        .map(|c| BECH32_CHARSET.iter().position(|&x| x == c).map(|i| i as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(BitcoinError::InvalidFormat)?;
+
+    let mut check_input: Vec<u8> = addr_hrp.bytes().map(|c| c >> 5).collect();
+    check_input.push(0);
+    check_input.extend(addr_hrp.bytes().map(|c| c & 31));
+    check_input.extend_from_slice(&data);
+
+    let version = data[0];
+    let expected_const = if version == 0 { 1 } else { 0x2bc830a3 };
+    if version > 16 || bech32_polymod(&check_input) != expected_const {
+        return Err(BitcoinError::InvalidFormat);
+    }
+
+    // Regroup 5-bit values (minus version and checksum) into bytes
+    let mut program = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for &v in &data[1..data.len() - 6] {
+        acc = (acc << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            program.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || (acc & ((1 << bits) - 1)) != 0 {
+        return Err(BitcoinError::InvalidFormat);
+    }
+
+    let valid_len = if version == 0 {
+        program.len() == 20 || program.len() == 32
+    } else {
+        (2..=40).contains(&program.len())
+    };
+    if !valid_len {
+        return Err(BitcoinError::InvalidFormat);
+    }
+
+    Ok((version, program))
+}
+
+// Decodes a base58check string into its payload (version byte included, checksum removed)
+fn decode_base58check(s: &str) -> Result<Vec<u8>, BitcoinError> {
+    let mut num: Vec<u8> = Vec::new(); // big-endian base-256 accumulator
+    for c in s.bytes() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|&x| x == c)
+            .ok_or(BitcoinError::InvalidFormat)? as u32;
+        for byte in num.iter_mut().rev() {
+            carry += (*byte as u32) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            num.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let leading_zeros = s.bytes().take_while(|&c| c == b'1').count();
+    let mut decoded = vec![0u8; leading_zeros];
+    decoded.extend_from_slice(&num);
+
+    if decoded.len() < 4 {
+        return Err(BitcoinError::InvalidFormat);
+    }
+    let (payload, checksum) = decoded.split_at(decoded.len() - 4);
+    if sha256d(payload)[..4] != *checksum {
+        return Err(BitcoinError::InvalidFormat);
+    }
+    Ok(payload.to_vec())
+}
+
+/// Standard output script templates recognised by `Script::script_type`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ScriptType {
+    P2PKH,
+    P2SH,
+    P2WPKH,
+    P2WSH,
+    P2TR,
+    OpReturn,
+    NonStandard,
+}
+
+/// One disassembled script element.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ScriptOp {
+    /// Direct push (opcodes 0x00..=0x4b); OP_0 is an empty push.
+    PushBytes(Vec<u8>),
+    PushData1(Vec<u8>),
+    PushData2(Vec<u8>),
+    PushData4(Vec<u8>),
+    /// Any non-push opcode.
+    Op(u8),
+}
+
+// An opcode and the data it pushes, borrowed from the script
+type RawOp<'a> = (u8, Option<&'a [u8]>);
+
+// Length-prefixed script body and the total bytes it takes, shared by `Script` and `ScriptRef`
+fn split_script(bytes: &[u8]) -> Result<(&[u8], usize), BitcoinError> {
+    let (length_prefix, prefix_len) = CompactSize::from_bytes(bytes)?;
+    let script_len = length_prefix.value as usize;
+
+    let total_len = prefix_len.saturating_add(script_len);
+    if bytes.len() < total_len {
+        return Err(BitcoinError::TruncatedScript {
+            offset: 0,
+            needed: total_len,
+        });
+    }
+
+    Ok((&bytes[prefix_len..total_len], total_len))
 }
 
+/// Consensus limit on the size of a script.
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Consensus limit on the size of a single pushed stack element.
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Script {
     pub bytes: Vec<u8>,
@@ -187,25 +696,363 @@ impl Script {
 
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut result = Vec::new();
+        let Ok(()) = self.encode(&mut result);
+        result
+    }
 
-        let length_prefix = CompactSize::new(self.bytes.len() as u64).to_bytes();
-        result.extend_from_slice(&length_prefix);
-        result.extend_from_slice(&self.bytes);
+    #[cfg(feature = "std")]
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.encode(&mut IoSink(writer))
+    }
 
-        result
+    fn encode<S: Sink>(&self, out: &mut S) -> Result<(), S::Error> {
+        CompactSize::new(self.bytes.len() as u64).encode(out)?;
+        out.put(&self.bytes)
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
-        let (length_prefix, prefix_len) = CompactSize::from_bytes(bytes)?;
-        let script_len = length_prefix.value as usize;
+        let (body, consumed) = split_script(bytes)?;
+        Ok((Script::new(body.to_vec()), consumed))
+    }
 
-        let total_len = prefix_len + script_len;
-        if bytes.len() < total_len {
-            return Err(BitcoinError::InsufficientBytes);
+    /// Like `from_bytes`, but a declared length over `MAX_SCRIPT_SIZE` (checked
+    /// before the body is read) or a push over `MAX_SCRIPT_ELEMENT_SIZE` is
+    /// `InvalidFormat`. A script whose final push is cut short is returned as-is.
+    pub fn from_bytes_checked(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        let (length_prefix, _) = CompactSize::from_bytes(bytes)?;
+        if length_prefix.value > MAX_SCRIPT_SIZE as u64 {
+            return Err(BitcoinError::InvalidFormat);
+        }
+        let (script, consumed) = Script::from_bytes(bytes)?;
+        if let Ok(ops) = script.parse_ops()
+            && ops
+                .iter()
+                .any(|(_, data)| data.is_some_and(|d| d.len() > MAX_SCRIPT_ELEMENT_SIZE))
+        {
+            return Err(BitcoinError::InvalidFormat);
+        }
+        Ok((script, consumed))
+    }
+
+    // Witness version of a BIP141 witness program (version opcode + one 2..=40 byte push)
+    fn witness_version(&self) -> Option<u8> {
+        let bytes = &self.bytes;
+        if bytes.len() < 4 || bytes.len() > 42 || bytes[1] as usize != bytes.len() - 2 {
+            return None;
+        }
+        match bytes[0] {
+            0x00 => Some(0),
+            op @ 0x51..=0x60 => Some(op - 0x50),
+            _ => None,
+        }
+    }
+
+    // Core's `HasValidOps`: every push is complete and no opcode is above OP_NOP10
+    fn has_valid_ops(&self) -> bool {
+        self.parse_ops()
+            .is_ok_and(|ops| ops.iter().all(|&(opcode, _)| opcode <= 0xB9))
+    }
+
+    // Walks the script as (opcode, pushed data) pairs; data is None for non-push opcodes
+    fn parse_ops(&self) -> Result<Vec<RawOp<'_>>, BitcoinError> {
+        let bytes = &self.bytes;
+        let mut ops = Vec::new();
+        let mut offset = 0;
+
+        while offset < bytes.len() {
+            let opcode = bytes[offset];
+            offset += 1;
+
+            let len_size = match opcode {
+                0x00 => {
+                    ops.push((opcode, Some(&bytes[0..0])));
+                    continue;
+                }
+                0x01..=0x4B => 0,
+                0x4C => 1,
+                0x4D => 2,
+                0x4E => 4,
+                _ => {
+                    ops.push((opcode, None));
+                    continue;
+                }
+            };
+
+            let push_len = if len_size == 0 {
+                opcode as usize
+            } else {
+                let len_bytes = bytes
+                    .get(offset..offset + len_size)
+                    .ok_or(BitcoinError::InvalidFormat)?;
+                offset += len_size;
+                let mut buf = [0u8; 4];
+                buf[..len_size].copy_from_slice(len_bytes);
+                u32::from_le_bytes(buf) as usize
+            };
+
+            let data = bytes
+                .get(offset..offset.saturating_add(push_len))
+                .ok_or(BitcoinError::InvalidFormat)?;
+            offset += push_len;
+            ops.push((opcode, Some(data)));
+        }
+
+        Ok(ops)
+    }
+
+    /// For a P2SH scriptPubKey, checks that the redeem script (the last push of a
+    /// push-only `script_sig`) hashes to the committed 20-byte script hash.
+    pub fn p2sh_matches(&self, script_sig: &Script) -> bool {
+        let bytes = &self.bytes;
+        if bytes.len() != 23 || bytes[0] != 0xA9 || bytes[1] != 0x14 || bytes[22] != 0x87 {
+            return false;
+        }
+
+        let ops = match script_sig.parse_ops() {
+            Ok(ops) => ops,
+            Err(_) => return false,
+        };
+        // IsPushOnly: every opcode up to OP_16 counts as a push
+        if ops.iter().any(|(opcode, _)| *opcode > 0x60) {
+            return false;
+        }
+
+        match ops.last() {
+            Some((_, Some(redeem_script))) => hash160(redeem_script) == bytes[2..22],
+            _ => false,
+        }
+    }
+
+    /// Builds the scriptPubKey paid by `address` (P2PKH, P2SH or a segwit witness
+    /// program), validating the checksum and that the address belongs to `network`.
+    pub fn from_address(address: &str, network: Network) -> Result<Script, BitcoinError> {
+        let hrp = network.bech32_hrp();
+        let is_segwit = address
+            .get(..hrp.len() + 1)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(&format!("{}1", hrp)));
+
+        if is_segwit {
+            let (version, program) = decode_segwit_address(address, hrp)?;
+            let mut bytes = vec![if version == 0 { 0x00 } else { 0x50 + version }];
+            bytes.push(program.len() as u8);
+            bytes.extend_from_slice(&program);
+            return Ok(Script::new(bytes));
+        }
+
+        let payload = decode_base58check(address)?;
+        if payload.len() != 21 {
+            return Err(BitcoinError::InvalidFormat);
+        }
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&payload[1..]);
+
+        if payload[0] == network.p2pkh_version() {
+            Ok(Script::p2pkh(&hash))
+        } else if payload[0] == network.p2sh_version() {
+            Ok(Script::p2sh(&hash))
+        } else {
+            Err(BitcoinError::InvalidFormat)
+        }
+    }
+
+    /// `OP_DUP OP_HASH160 <pubkey_hash> OP_EQUALVERIFY OP_CHECKSIG`
+    pub fn p2pkh(pubkey_hash: &[u8; 20]) -> Script {
+        let mut bytes = vec![0x76, 0xA9, 0x14];
+        bytes.extend_from_slice(pubkey_hash);
+        bytes.extend_from_slice(&[0x88, 0xAC]);
+        Script::new(bytes)
+    }
+
+    /// `OP_HASH160 <script_hash> OP_EQUAL`
+    pub fn p2sh(script_hash: &[u8; 20]) -> Script {
+        let mut bytes = vec![0xA9, 0x14];
+        bytes.extend_from_slice(script_hash);
+        bytes.push(0x87);
+        Script::new(bytes)
+    }
+
+    /// `OP_0 <pubkey_hash>`
+    pub fn p2wpkh(pubkey_hash: &[u8; 20]) -> Script {
+        let mut bytes = vec![0x00, 0x14];
+        bytes.extend_from_slice(pubkey_hash);
+        Script::new(bytes)
+    }
+
+    /// Whether any pushed DER signature has an S value above half the curve order,
+    /// which BIP 62 low-S relay policy rejects.
+    pub fn has_non_low_s_signature(&self) -> bool {
+        let ops = match self.parse_ops() {
+            Ok(ops) => ops,
+            Err(_) => return false,
+        };
+        ops.iter()
+            .filter_map(|(_, data)| der_signature_s(data.as_ref()?))
+            .any(|s| s.len() > 32 || (s.len() == 32 && s > &SECP256K1_HALF_ORDER[..]))
+    }
+
+    /// Whether any push is a 65-byte uncompressed public key (`0x04` prefix),
+    /// which is non-standard in segwit spends.
+    pub fn has_uncompressed_pubkey(&self) -> bool {
+        let ops = match self.parse_ops() {
+            Ok(ops) => ops,
+            Err(_) => return false,
+        };
+        ops.iter()
+            .filter_map(|(_, data)| *data)
+            .any(|data| data.len() == 65 && data[0] == 0x04)
+    }
+
+    /// Splits the script into opcodes and pushes. Returns `InvalidFormat` if a push
+    /// claims more bytes than remain.
+    pub fn disassemble(&self) -> Result<Vec<ScriptOp>, BitcoinError> {
+        Ok(self
+            .parse_ops()?
+            .into_iter()
+            .map(|(opcode, data)| match (opcode, data) {
+                (0x4C, Some(data)) => ScriptOp::PushData1(data.to_vec()),
+                (0x4D, Some(data)) => ScriptOp::PushData2(data.to_vec()),
+                (0x4E, Some(data)) => ScriptOp::PushData4(data.to_vec()),
+                (_, Some(data)) => ScriptOp::PushBytes(data.to_vec()),
+                (opcode, None) => ScriptOp::Op(opcode),
+            })
+            .collect())
+    }
+
+    /// Lock time of a bare CLTV script (`<locktime> OP_CHECKLOCKTIMEVERIFY OP_DROP ...`),
+    /// or `None` if the script does not start that way.
+    pub fn cltv_locktime(&self) -> Option<u32> {
+        let ops = self.disassemble().ok()?;
+        if ops.len() < 3 || ops[1] != ScriptOp::Op(0xB1) || ops[2] != ScriptOp::Op(0x75) {
+            return None;
+        }
+
+        match &ops[0] {
+            ScriptOp::Op(op @ 0x51..=0x60) => Some((op - 0x50) as u32),
+            // CLTV accepts script numbers of up to 5 bytes, little-endian with a sign bit
+            ScriptOp::PushBytes(n) if n.len() <= 5 => {
+                if n.last().is_some_and(|&b| b & 0x80 != 0) {
+                    return None;
+                }
+                let value = n.iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+                u32::try_from(value).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Classifies the script by its canonical scriptPubKey byte pattern.
+    pub fn script_type(&self) -> ScriptType {
+        match self.bytes.as_slice() {
+            [0x76, 0xA9, 0x14, hash @ .., 0x88, 0xAC] if hash.len() == 20 => ScriptType::P2PKH,
+            [0xA9, 0x14, hash @ .., 0x87] if hash.len() == 20 => ScriptType::P2SH,
+            [0x00, 0x14, program @ ..] if program.len() == 20 => ScriptType::P2WPKH,
+            [0x00, 0x20, program @ ..] if program.len() == 32 => ScriptType::P2WSH,
+            [0x51, 0x20, program @ ..] if program.len() == 32 => ScriptType::P2TR,
+            [0x6A, ..] => ScriptType::OpReturn,
+            _ => ScriptType::NonStandard,
+        }
+    }
+
+    // Core's dust threshold at the default 3 sat/vB dust relay fee: the cost of
+    // this output plus a typical input spending it (546 for P2PKH, 294 for P2WPKH)
+    fn dust_threshold(&self) -> u64 {
+        let output_size = 8 + CompactSize::new(self.len() as u64).serialized_len() + self.len();
+        // Outpoint, scriptSig length, sequence, plus a ~107-byte signature and pubkey,
+        // discounted to a quarter when it goes in the witness
+        let input_size = if self.witness_version().is_some() {
+            32 + 4 + 1 + 107 / 4 + 4
+        } else {
+            32 + 4 + 1 + 107 + 4
+        };
+        3 * (output_size + input_size) as u64
+    }
+
+    /// Legacy (inaccurate) sigop count as in Core's `GetSigOpCount(false)`:
+    /// OP_CHECKSIG(VERIFY) counts 1 and OP_CHECKMULTISIG(VERIFY) counts 20. A
+    /// script with a cut-off push counts 0.
+    pub fn sigop_count(&self) -> usize {
+        let ops = match self.parse_ops() {
+            Ok(ops) => ops,
+            Err(_) => return 0,
+        };
+        ops.iter()
+            .map(|(opcode, _)| match opcode {
+                0xAC | 0xAD => 1,
+                0xAE | 0xAF => 20,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    // (m, n) of a bare `OP_m <pubkey>... OP_n OP_CHECKMULTISIG` script
+    fn multisig_threshold(&self) -> Option<(u8, u8)> {
+        let ops = self.parse_ops().ok()?;
+        match ops.as_slice() {
+            [
+                (m @ 0x51..=0x60, None),
+                keys @ ..,
+                (n @ 0x51..=0x60, None),
+                (0xAE, None),
+            ] if keys.len() == (n - 0x50) as usize
+                && m <= n
+                && keys
+                    .iter()
+                    .all(|(_, key)| key.is_some_and(|k| matches!(k.len(), 33 | 65))) =>
+            {
+                Some((m - 0x50, n - 0x50))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Sighash type byte appended to a DER-encoded ECDSA signature push, or `None`
+/// if the bytes are not a DER sequence followed by exactly one trailing byte.
+pub fn signature_sighash_type(der_sig_with_type: &[u8]) -> Option<u8> {
+    match der_sig_with_type {
+        [0x30, der_len, .., sighash] if *der_len as usize + 3 == der_sig_with_type.len() => {
+            Some(*sighash)
         }
+        _ => None,
+    }
+}
+
+/// scriptPubKey sizes for paying the same 20-byte hash with each script type.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ScriptSizeComparison {
+    pub p2pkh: usize,
+    pub p2sh: usize,
+    pub p2wpkh: usize,
+}
+
+pub fn compare_output_sizes(payload_hash: &[u8; 20]) -> ScriptSizeComparison {
+    ScriptSizeComparison {
+        p2pkh: Script::p2pkh(payload_hash).len(),
+        p2sh: Script::p2sh(payload_hash).len(),
+        p2wpkh: Script::p2wpkh(payload_hash).len(),
+    }
+}
+
+/// Borrowed view of a script inside a larger buffer, for parsing without a copy
+/// per script.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ScriptRef<'a> {
+    pub bytes: &'a [u8],
+}
+
+impl<'a> ScriptRef<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        ScriptRef { bytes }
+    }
+
+    /// Same format and errors as `Script::from_bytes`, borrowing the body.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<(Self, usize), BitcoinError> {
+        let (body, consumed) = split_script(bytes)?;
+        Ok((ScriptRef::new(body), consumed))
+    }
 
-        let script_bytes = bytes[prefix_len..total_len].to_vec();
-        Ok((Script::new(script_bytes), total_len))
+    pub fn to_owned(&self) -> Script {
+        Script::new(self.bytes.to_vec())
     }
 }
 
@@ -216,6 +1063,31 @@ impl Deref for Script {
         &self.bytes
     }
 }
+// Largest low-S DER signature (71 bytes) plus its sighash byte
+const DUMMY_SIGNATURE_LEN: usize = 72;
+const COMPRESSED_PUBKEY_LEN: usize = 33;
+
+/// Spend templates understood by `TransactionInput::with_dummy_signature`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InputType {
+    P2PKH,
+    P2WPKH,
+}
+
+impl InputType {
+    /// Worst-case witness stack for this spend type. Witnesses live on the
+    /// transaction, so this goes into `BitcoinTransaction::witnesses`.
+    pub fn dummy_witness(&self) -> Vec<Vec<u8>> {
+        match self {
+            InputType::P2PKH => Vec::new(),
+            InputType::P2WPKH => vec![
+                vec![0u8; DUMMY_SIGNATURE_LEN],
+                vec![0u8; COMPRESSED_PUBKEY_LEN],
+            ],
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct TransactionInput {
     pub previous_output: OutPoint,
@@ -234,20 +1106,30 @@ impl TransactionInput {
 
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut result = Vec::new();
-        result.extend_from_slice(&self.previous_output.to_bytes());
-        result.extend_from_slice(&self.script_sig.to_bytes());
-        result.extend_from_slice(&self.sequence.to_le_bytes());
+        let Ok(()) = self.encode(&mut result);
         result
     }
 
+    #[cfg(feature = "std")]
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.encode(&mut IoSink(writer))
+    }
+
+    fn encode<S: Sink>(&self, out: &mut S) -> Result<(), S::Error> {
+        self.previous_output.encode(out)?;
+        self.script_sig.encode(out)?;
+        out.put(&self.sequence.to_le_bytes())
+    }
+
     pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
         let (out_point, out_len) = OutPoint::from_bytes(bytes)?;
-        let (script_sig, script_len) = Script::from_bytes(&bytes[out_len..])?;
+        let (script_sig, script_len) =
+            Script::from_bytes(&bytes[out_len..]).map_err(|e| e.shifted(out_len))?;
 
         let offset = out_len + script_len;
 
         if bytes.len() < offset + 4 {
-            return Err(BitcoinError::InsufficientBytes);
+            return Err(BitcoinError::InsufficientBytes { offset, needed: 4 });
         }
 
         let mut seq_bytes = [0u8; 4];
@@ -263,66 +1145,579 @@ impl TransactionInput {
             offset + 4,
         ))
     }
+
+    /// Witness version spent by this input given the prevout's scriptPubKey
+    /// (0 for P2WPKH/P2WSH, 1 for P2TR). Native spends only: a non-empty
+    /// scriptSig means the input is not a bare witness program spend.
+    pub fn segwit_version(&self, prevout_script: &Script) -> Option<u8> {
+        if !self.script_sig.is_empty() {
+            return None;
+        }
+        prevout_script.witness_version()
+    }
+
+    /// Copy of this input with a maximally-sized dummy scriptSig for `input_type`,
+    /// for conservative size estimation before signing. Pair it with
+    /// `InputType::dummy_witness` for segwit spends.
+    pub fn with_dummy_signature(&self, input_type: InputType) -> TransactionInput {
+        let script_sig = match input_type {
+            InputType::P2PKH => {
+                let mut bytes = vec![DUMMY_SIGNATURE_LEN as u8];
+                bytes.extend_from_slice(&[0u8; DUMMY_SIGNATURE_LEN]);
+                bytes.push(COMPRESSED_PUBKEY_LEN as u8);
+                bytes.extend_from_slice(&[0u8; COMPRESSED_PUBKEY_LEN]);
+                Script::new(bytes)
+            }
+            InputType::P2WPKH => Script::new(Vec::new()),
+        };
+
+        TransactionInput {
+            script_sig,
+            ..self.clone()
+        }
+    }
+
+    /// For a P2SH-wrapped segwit spend, the type of the witness program pushed as
+    /// the redeem script (`P2WPKH` or `P2WSH`). The scriptSig must be exactly one
+    /// direct push.
+    pub fn nested_segwit_type(&self) -> Option<ScriptType> {
+        let redeem_script = match self.script_sig.bytes.as_slice() {
+            [len, redeem @ ..] if *len as usize == redeem.len() => Script::new(redeem.to_vec()),
+            _ => return None,
+        };
+        match redeem_script.script_type() {
+            kind @ (ScriptType::P2WPKH | ScriptType::P2WSH) => Some(kind),
+            _ => None,
+        }
+    }
+
+    /// Readable label for what this input spends, from the prevout's scriptPubKey
+    /// (and the scriptSig for P2SH-wrapped segwit): e.g. "P2PKH", "P2SH-P2WPKH",
+    /// "P2TR" or "2-of-3 multisig". Telling a Taproot key-path from a script-path
+    /// spend needs the witness, so both are "P2TR". "unknown" without a prevout.
+    pub fn describe_spend(&self, prevout: Option<&TransactionOutput>) -> String {
+        let script = match prevout {
+            Some(prevout) => &prevout.script_pubkey,
+            None => return "unknown".to_string(),
+        };
+        let label = match script.script_type() {
+            ScriptType::P2PKH => "P2PKH",
+            ScriptType::P2SH => match self.nested_segwit_type() {
+                Some(ScriptType::P2WPKH) => "P2SH-P2WPKH",
+                Some(_) => "P2SH-P2WSH",
+                None => "P2SH",
+            },
+            ScriptType::P2WPKH => "P2WPKH",
+            ScriptType::P2WSH => "P2WSH",
+            ScriptType::P2TR => "P2TR",
+            ScriptType::OpReturn => "OP_RETURN",
+            ScriptType::NonStandard => match script.multisig_threshold() {
+                Some((m, n)) => return format!("{}-of-{} multisig", m, n),
+                None => "non-standard",
+            },
+        };
+        label.to_string()
+    }
+}
+
+// Flag bits in the leading byte of an `ExtendedTransactionInput` encoding
+const EXT_HAS_VALUE: u8 = 0x01;
+const EXT_HAS_PREVOUT_SCRIPT: u8 = 0x02;
+
+/// Wallet storage format: a `TransactionInput` plus the prevout data needed to
+/// sign it. Encoded as a flag byte, the plain input, then the value (8 bytes LE)
+/// and prevout script when their flag is set. Not a consensus encoding.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ExtendedTransactionInput {
+    pub input: TransactionInput,
+    pub value: Option<u64>,
+    pub prevout_script: Option<Script>,
+}
+
+impl ExtendedTransactionInput {
+    pub fn new(
+        input: TransactionInput,
+        value: Option<u64>,
+        prevout_script: Option<Script>,
+    ) -> Self {
+        ExtendedTransactionInput {
+            input,
+            value,
+            prevout_script,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flags = 0u8;
+        if self.value.is_some() {
+            flags |= EXT_HAS_VALUE;
+        }
+        if self.prevout_script.is_some() {
+            flags |= EXT_HAS_PREVOUT_SCRIPT;
+        }
+
+        let mut result = vec![flags];
+        result.extend_from_slice(&self.input.to_bytes());
+        if let Some(value) = self.value {
+            result.extend_from_slice(&value.to_le_bytes());
+        }
+        if let Some(script) = &self.prevout_script {
+            result.extend_from_slice(&script.to_bytes());
+        }
+        result
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        let flags = *bytes.first().ok_or(BitcoinError::InsufficientBytes {
+            offset: 0,
+            needed: 1,
+        })?;
+        if flags & !(EXT_HAS_VALUE | EXT_HAS_PREVOUT_SCRIPT) != 0 {
+            return Err(BitcoinError::InvalidFormat);
+        }
+
+        let (input, input_len) =
+            TransactionInput::from_bytes(&bytes[1..]).map_err(|e| e.shifted(1))?;
+        let mut offset = 1 + input_len;
+
+        let value = if flags & EXT_HAS_VALUE != 0 {
+            if bytes.len() < offset + 8 {
+                return Err(BitcoinError::InsufficientBytes { offset, needed: 8 });
+            }
+            let mut value_bytes = [0u8; 8];
+            value_bytes.copy_from_slice(&bytes[offset..offset + 8]);
+            offset += 8;
+            Some(u64::from_le_bytes(value_bytes))
+        } else {
+            None
+        };
+
+        let prevout_script = if flags & EXT_HAS_PREVOUT_SCRIPT != 0 {
+            let (script, script_len) =
+                Script::from_bytes(&bytes[offset..]).map_err(|e| e.shifted(offset))?;
+            offset += script_len;
+            Some(script)
+        } else {
+            None
+        };
+
+        Ok((
+            ExtendedTransactionInput {
+                input,
+                value,
+                prevout_script,
+            },
+            offset,
+        ))
+    }
+}
+
+/// Long-standing default relay limit (`-datacarriersize`) on the size of an
+/// OP_RETURN scriptPubKey, opcodes included.
+pub const MAX_OP_RETURN_SIZE: usize = 83;
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TransactionOutput {
+    pub value: u64,
+    pub script_pubkey: Script,
+}
+
+impl TransactionOutput {
+    pub fn new(value: u64, script_pubkey: Script) -> Self {
+        TransactionOutput {
+            value,
+            script_pubkey,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        let Ok(()) = self.encode(&mut result);
+        result
+    }
+
+    #[cfg(feature = "std")]
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.encode(&mut IoSink(writer))
+    }
+
+    fn encode<S: Sink>(&self, out: &mut S) -> Result<(), S::Error> {
+        out.put(&self.value.to_le_bytes())?;
+        self.script_pubkey.encode(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        if bytes.len() < 8 {
+            return Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 8,
+            });
+        }
+
+        let mut value_bytes = [0u8; 8];
+        value_bytes.copy_from_slice(&bytes[..8]);
+        let value = u64::from_le_bytes(value_bytes);
+
+        let (script_pubkey, script_len) =
+            Script::from_bytes(&bytes[8..]).map_err(|e| e.shifted(8))?;
+
+        Ok((
+            TransactionOutput {
+                value,
+                script_pubkey,
+            },
+            8 + script_len,
+        ))
+    }
+
+    /// Lock time if the scriptPubKey is a bare CLTV script, see `Script::cltv_locktime`.
+    pub fn cltv_locktime(&self) -> Option<u32> {
+        self.script_pubkey.cltv_locktime()
+    }
+
+    /// Zero-value OP_RETURN output carrying each chunk as its own minimal push.
+    /// `InvalidFormat` if the script would exceed `MAX_OP_RETURN_SIZE`.
+    pub fn op_return_multi(chunks: &[&[u8]]) -> Result<Self, BitcoinError> {
+        let mut bytes = vec![0x6A];
+        for chunk in chunks {
+            match chunk.len() {
+                0 => bytes.push(0x00),
+                len @ 1..=0x4B => bytes.push(len as u8),
+                len @ 0x4C..=0xFF => bytes.extend_from_slice(&[0x4C, len as u8]),
+                _ => return Err(BitcoinError::InvalidFormat),
+            }
+            bytes.extend_from_slice(chunk);
+            if bytes.len() > MAX_OP_RETURN_SIZE {
+                return Err(BitcoinError::InvalidFormat);
+            }
+        }
+        Ok(TransactionOutput::new(0, Script::new(bytes)))
+    }
+}
+
+/// Default mempool limits on a transaction's descendant package (the transaction
+/// itself plus all of its unconfirmed descendants).
+pub const DEFAULT_DESCENDANT_LIMIT: usize = 25;
+pub const DEFAULT_DESCENDANT_SIZE_LIMIT_VB: usize = 101_000;
+
+/// Heuristic transaction shape, see `BitcoinTransaction::shape`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TxShape {
+    Consolidation,
+    Payment,
+    Batch,
+    Unknown,
+}
+
+// Input/output count at which `shape` starts calling a side "many"
+const SHAPE_MANY: usize = 3;
+
+/// Coarse vsize bucket for fee UX, see `BitcoinTransaction::size_category`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SizeCategory {
+    Tiny,
+    Small,
+    Medium,
+    Large,
+    Huge,
+}
+
+// Inclusive upper vsize bounds of Tiny, Small, Medium and Large. Tiny fits a
+// one-input segwit payment, Large ends at the 100,000 vB (400,000 WU) standard
+// size limit, so Huge is always non-standard.
+const SIZE_CATEGORY_BOUNDS: [usize; 4] = [250, 1_000, 10_000, 100_000];
+
+// Smallest serialized input: 36-byte outpoint, 1-byte empty script, 4-byte sequence
+const MIN_INPUT_SIZE: usize = 41;
+
+// Smallest serialized output: 8-byte value, 1-byte empty script
+const MIN_OUTPUT_SIZE: usize = 9;
+
+/// Policy caps for `BitcoinTransaction::from_bytes_with_limits`. The default
+/// imposes none beyond what the buffer can hold.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DecodeLimits {
+    pub max_outputs: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_outputs: usize::MAX,
+        }
+    }
+}
+
+// Decodes one witness stack: a CompactSize item count followed by length-prefixed items
+fn decode_witness_stack(bytes: &[u8]) -> Result<(Vec<Vec<u8>>, usize), BitcoinError> {
+    let (count, mut offset) = CompactSize::from_bytes(bytes)?;
+    let item_count = count.value as usize;
+
+    // Each item takes at least its one-byte length prefix
+    let mut items = Vec::with_capacity(item_count.min(bytes.len() - offset));
+    for _ in 0..item_count {
+        let (len, len_size) =
+            CompactSize::from_bytes(&bytes[offset..]).map_err(|e| e.shifted(offset))?;
+        offset += len_size;
+        let end = offset.saturating_add(len.value as usize);
+        if bytes.len() < end {
+            return Err(BitcoinError::InsufficientBytes {
+                offset,
+                needed: len.value as usize,
+            });
+        }
+        items.push(bytes[offset..end].to_vec());
+        offset = end;
+    }
+
+    Ok((items, offset))
+}
+
+fn encode_witness_stack<S: Sink>(stack: &[Vec<u8>], out: &mut S) -> Result<(), S::Error> {
+    CompactSize::new(stack.len() as u64).encode(out)?;
+    for item in stack {
+        CompactSize::new(item.len() as u64).encode(out)?;
+        out.put(item)?;
+    }
+    Ok(())
+}
+
+// BIP341: with at least two witness items, a last item starting with 0x50 is the
+// annex. Returns the remaining items and the annex, if any.
+fn split_annex(stack: &[Vec<u8>]) -> (&[Vec<u8>], Option<&[u8]>) {
+    match stack {
+        [rest @ .., last] if !rest.is_empty() && last.first() == Some(&0x50) => {
+            (rest, Some(last.as_slice()))
+        }
+        _ => (stack, None),
+    }
+}
+
+/// Value accounting for a transaction, see `BitcoinTransaction::value_flow`.
+/// `total_input == total_output + fee` always holds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ValueFlow {
+    pub total_input: u64,
+    pub total_output: u64,
+    pub fee: u64,
+}
+
+// Per-index `{"old", "new"}` pairs for the positions where two lists differ, with
+// `null` on the side that has no entry; `None` if the lists are equal
+fn diff_list<T: Serialize + PartialEq>(old: &[T], new: &[T]) -> Option<serde_json::Value> {
+    let mut changes = serde_json::Map::new();
+    for i in 0..old.len().max(new.len()) {
+        let (a, b) = (old.get(i), new.get(i));
+        if a != b {
+            changes.insert(i.to_string(), serde_json::json!({ "old": a, "new": b }));
+        }
+    }
+    (!changes.is_empty()).then_some(serde_json::Value::Object(changes))
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct BitcoinTransaction {
     pub version: u32,
     pub inputs: Vec<TransactionInput>,
+    pub outputs: Vec<TransactionOutput>,
+    /// One witness stack per input (BIP144); empty for legacy transactions.
+    #[serde(default)]
+    pub witnesses: Vec<Vec<Vec<u8>>>,
     pub lock_time: u32,
 }
 impl BitcoinTransaction {
-    /// Constructs a Bitcoin transaction from version, inputs, and lock_time.
-    pub fn new(version: u32, inputs: Vec<TransactionInput>, lock_time: u32) -> Self {
+    /// Constructs a Bitcoin transaction from version, inputs, outputs, and lock_time.
+    /// Witnesses start out empty.
+    pub fn new(
+        version: u32,
+        inputs: Vec<TransactionInput>,
+        outputs: Vec<TransactionOutput>,
+        lock_time: u32,
+    ) -> Self {
         BitcoinTransaction {
             version,
             inputs,
+            outputs,
+            witnesses: Vec::new(),
             lock_time,
         }
     }
 
+    /// True if any input carries witness items, i.e. the wtxid differs from the
+    /// txid. BIP144 serialization is only used in that case; stacks beyond the
+    /// input count are never written, so they don't count.
+    pub fn has_witness(&self) -> bool {
+        self.witnesses
+            .iter()
+            .take(self.inputs.len())
+            .any(|stack| !stack.is_empty())
+    }
+
+    /// BIP144 serialization (marker, flag, witnesses after the outputs) when any
+    /// input has witness data, legacy serialization otherwise. An input-less legacy
+    /// transaction encodes its input count as `0x00`, the same byte as the SegWit
+    /// marker; `from_bytes` tries SegWit first and only decodes such bytes as
+    /// legacy when that covers the whole buffer, so they must not be followed by
+    /// unrelated data.
     pub fn to_bytes(&self) -> Vec<u8> {
+        self.serialize(self.has_witness())
+    }
+
+    /// Writes the same bytes as `to_bytes` without building an intermediate `Vec`.
+    #[cfg(feature = "std")]
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.encode(&mut IoSink(writer), self.has_witness())
+    }
+
+    fn serialize(&self, segwit: bool) -> Vec<u8> {
         let mut result = Vec::new();
+        let Ok(()) = self.encode(&mut result, segwit);
+        result
+    }
 
-        result.extend_from_slice(&self.version.to_le_bytes());
+    fn encode<S: Sink>(&self, out: &mut S, segwit: bool) -> Result<(), S::Error> {
+        out.put(&self.version.to_le_bytes())?;
 
-        let input_count = CompactSize::new(self.inputs.len() as u64);
-        result.extend_from_slice(&input_count.to_bytes());
+        if segwit {
+            out.put(&[0x00, 0x01])?;
+        }
 
+        CompactSize::new(self.inputs.len() as u64).encode(out)?;
         for input in &self.inputs {
-            result.extend_from_slice(&input.to_bytes());
+            input.encode(out)?;
         }
 
-        result.extend_from_slice(&self.lock_time.to_le_bytes());
+        CompactSize::new(self.outputs.len() as u64).encode(out)?;
+        for output in &self.outputs {
+            output.encode(out)?;
+        }
 
-        result
+        if segwit {
+            self.encode_witnesses(out)?;
+        }
+
+        out.put(&self.lock_time.to_le_bytes())
+    }
+
+    // One stack per input, an empty one (a single 0x00) where none is stored
+    fn encode_witnesses<S: Sink>(&self, out: &mut S) -> Result<(), S::Error> {
+        for i in 0..self.inputs.len() {
+            let stack = self.witnesses.get(i).map(Vec::as_slice).unwrap_or(&[]);
+            encode_witness_stack(stack, out)?;
+        }
+        Ok(())
     }
 
+    /// Decodes either serialization. A `00 01` after the version is read as the
+    /// SegWit marker and flag first. The bytes are decoded again as legacy, where
+    /// `00` is an empty input list, if that yields witnesses that are all empty
+    /// (which BIP144 forbids), or, as in Core's `DecodeHexTx`, if it fails but the
+    /// legacy decode consumes the whole buffer with well-formed output scripts.
+    /// Otherwise the SegWit error is returned, so a truncated SegWit transaction
+    /// is not mistaken for an input-less legacy one.
     pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        BitcoinTransaction::from_bytes_with_limits(bytes, &DecodeLimits::default())
+    }
+
+    /// `from_bytes` with policy caps; a declared count over a limit is
+    /// `InvalidFormat` before any of the counted items are decoded.
+    pub fn from_bytes_with_limits(
+        bytes: &[u8],
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        if bytes.len() >= 6 && bytes[4] == 0x00 && bytes[5] == 0x01 {
+            return match BitcoinTransaction::decode(bytes, true, limits) {
+                Ok((tx, consumed)) if tx.witnesses.iter().any(|stack| !stack.is_empty()) => {
+                    Ok((tx, consumed))
+                }
+                Ok(_) => BitcoinTransaction::decode(bytes, false, limits)
+                    .map_err(|_| BitcoinError::InvalidFormat),
+                Err(segwit_err) => match BitcoinTransaction::decode(bytes, false, limits) {
+                    Ok((tx, consumed))
+                        if consumed == bytes.len()
+                            && tx.outputs.iter().all(|o| o.script_pubkey.has_valid_ops()) =>
+                    {
+                        Ok((tx, consumed))
+                    }
+                    _ => Err(segwit_err),
+                },
+            };
+        }
+        BitcoinTransaction::decode(bytes, false, limits)
+    }
+
+    fn decode(
+        bytes: &[u8],
+        segwit: bool,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
         let mut offset = 0;
 
         if bytes.len() < 4 {
-            return Err(BitcoinError::InsufficientBytes);
+            return Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 4,
+            });
         }
         let mut ver_bytes = [0u8; 4];
         ver_bytes.copy_from_slice(&bytes[0..4]);
         let version = u32::from_le_bytes(ver_bytes);
         offset += 4;
 
-        let (input_count_cs, input_count_len) = CompactSize::from_bytes(&bytes[offset..])?;
+        if segwit {
+            // Marker and flag, already checked by the caller
+            offset += 2;
+        }
+
+        let (input_count_cs, input_count_len) =
+            CompactSize::from_bytes(&bytes[offset..]).map_err(|e| e.shifted(offset))?;
         let input_count = input_count_cs.value as usize;
         offset += input_count_len;
 
-        let mut inputs = Vec::with_capacity(input_count);
+        // Never trust the declared count for pre-allocation: each input needs at least
+        // MIN_INPUT_SIZE bytes, so the remaining buffer bounds how many can exist
+        let max_inputs = (bytes.len() - offset) / MIN_INPUT_SIZE;
+        let mut inputs = Vec::with_capacity(input_count.min(max_inputs));
         for _ in 0..input_count {
-            let (input, input_len) = TransactionInput::from_bytes(&bytes[offset..])?;
+            let (input, input_len) =
+                TransactionInput::from_bytes(&bytes[offset..]).map_err(|e| e.shifted(offset))?;
             inputs.push(input);
             offset += input_len;
         }
 
+        let (output_count_cs, output_count_len) =
+            CompactSize::from_bytes(&bytes[offset..]).map_err(|e| e.shifted(offset))?;
+        let output_count = output_count_cs.value as usize;
+        offset += output_count_len;
+
+        if output_count > limits.max_outputs {
+            return Err(BitcoinError::InvalidFormat);
+        }
+
+        // Same bound as for inputs, with MIN_OUTPUT_SIZE per output
+        let max_outputs = (bytes.len() - offset) / MIN_OUTPUT_SIZE;
+        let mut outputs = Vec::with_capacity(output_count.min(max_outputs));
+        for _ in 0..output_count {
+            let (output, output_len) =
+                TransactionOutput::from_bytes(&bytes[offset..]).map_err(|e| e.shifted(offset))?;
+            outputs.push(output);
+            offset += output_len;
+        }
+
+        let mut witnesses = Vec::new();
+        if segwit {
+            witnesses.reserve(inputs.len());
+            for _ in 0..inputs.len() {
+                let (stack, stack_len) =
+                    decode_witness_stack(&bytes[offset..]).map_err(|e| e.shifted(offset))?;
+                witnesses.push(stack);
+                offset += stack_len;
+            }
+        }
+
         // Lock time
         if bytes.len() < offset + 4 {
-            return Err(BitcoinError::InsufficientBytes);
+            return Err(BitcoinError::InsufficientBytes { offset, needed: 4 });
         }
         let mut lt_bytes = [0u8; 4];
         lt_bytes.copy_from_slice(&bytes[offset..offset + 4]);
@@ -333,11 +1728,990 @@ impl BitcoinTransaction {
             BitcoinTransaction {
                 version,
                 inputs,
+                outputs,
+                witnesses,
                 lock_time,
             },
             offset,
         ))
     }
+
+    /// Consensus bytes for an on-disk cache; smaller and faster to load than serde JSON.
+    pub fn to_consensus_cached(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    /// Loads a transaction written by `to_consensus_cached`. The entry must contain
+    /// exactly one transaction.
+    pub fn from_consensus_cached(bytes: &[u8]) -> Result<Self, BitcoinError> {
+        BitcoinTransaction::from_bytes_exact(bytes)
+    }
+
+    /// Decodes a buffer that must hold exactly one transaction; trailing bytes are
+    /// `InvalidFormat`.
+    pub fn from_bytes_exact(bytes: &[u8]) -> Result<Self, BitcoinError> {
+        require_exact(bytes, BitcoinTransaction::from_bytes(bytes)?)
+    }
+
+    /// Decodes a transaction at the cursor's position and advances the cursor past it.
+    /// On error the cursor is left untouched.
+    #[cfg(feature = "std")]
+    pub fn decode_from_cursor(cursor: &mut Cursor<&[u8]>) -> Result<Self, BitcoinError> {
+        let bytes = *cursor.get_ref();
+        let start = cursor.position() as usize;
+        if start > bytes.len() {
+            return Err(BitcoinError::InsufficientBytes {
+                offset: start,
+                needed: 1,
+            });
+        }
+
+        // Offsets in errors are positions in the cursor's whole buffer
+        let (tx, consumed) =
+            BitcoinTransaction::from_bytes(&bytes[start..]).map_err(|e| e.shifted(start))?;
+        cursor.set_position((start + consumed) as u64);
+        Ok(tx)
+    }
+
+    /// Emits a snippet that can be pasted into a test: the raw hex plus assertions
+    /// on the parsed fields.
+    pub fn to_test_vector(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!(
+            "let raw = hex::decode(\"{}\").unwrap();\n",
+            hex::encode(self.to_bytes())
+        ));
+        out.push_str("let (tx, _) = BitcoinTransaction::from_bytes(&raw).unwrap();\n");
+        out.push_str(&format!("assert_eq!(tx.version, {});\n", self.version));
+        out.push_str(&format!("assert_eq!(tx.lock_time, {});\n", self.lock_time));
+        out.push_str(&format!(
+            "assert_eq!(tx.inputs.len(), {});\n",
+            self.inputs.len()
+        ));
+        out.push_str(&format!(
+            "assert_eq!(tx.outputs.len(), {});\n",
+            self.outputs.len()
+        ));
+        for (i, input) in self.inputs.iter().enumerate() {
+            out.push_str(&format!(
+                "assert_eq!(tx.inputs[{}].previous_output.vout, {});\n",
+                i, input.previous_output.vout
+            ));
+            out.push_str(&format!(
+                "assert_eq!(tx.inputs[{}].sequence, {:#010x});\n",
+                i, input.sequence
+            ));
+        }
+        out
+    }
+
+    /// Serializes the transaction preceded by its byte length as a CompactSize.
+    pub fn to_length_prefixed(&self) -> Vec<u8> {
+        let tx_bytes = self.to_bytes();
+        let mut result = CompactSize::new(tx_bytes.len() as u64).to_bytes();
+        result.extend_from_slice(&tx_bytes);
+        result
+    }
+
+    /// Decodes a CompactSize byte length followed by exactly that many transaction bytes.
+    pub fn from_length_prefixed(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        let (length, prefix_len) = CompactSize::from_bytes(bytes)?;
+        let tx_len = length.value as usize;
+
+        let total_len = prefix_len.saturating_add(tx_len);
+        if bytes.len() < total_len {
+            return Err(BitcoinError::InsufficientBytes {
+                offset: prefix_len,
+                needed: tx_len,
+            });
+        }
+
+        let (tx, consumed) = BitcoinTransaction::from_bytes(&bytes[prefix_len..total_len])
+            .map_err(|e| e.shifted(prefix_len))?;
+        if consumed != tx_len {
+            return Err(BitcoinError::InvalidFormat);
+        }
+
+        Ok((tx, total_len))
+    }
+
+    /// Whether any input spends `outpoint`.
+    pub fn spends_outpoint(&self, outpoint: &OutPoint) -> bool {
+        self.inputs
+            .iter()
+            .any(|input| &input.previous_output == outpoint)
+    }
+
+    /// Indices of inputs whose previous transaction the caller identifies as a
+    /// coinbase, so maturity can be checked against the caller's own UTXO data.
+    pub fn coinbase_inputs(&self, is_coinbase: impl Fn(&Txid) -> bool) -> Vec<usize> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| is_coinbase(&input.previous_output.txid))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Flags pinning risk: the descendant package (counted including this
+    /// transaction, as the mempool does) has reached the default count or vsize
+    /// limit, so no further child can be attached.
+    pub fn descendant_limit_risk(&self, descendant_count: usize, descendant_vsize: usize) -> bool {
+        descendant_count >= DEFAULT_DESCENDANT_LIMIT
+            || descendant_vsize >= DEFAULT_DESCENDANT_SIZE_LIMIT_VB
+    }
+
+    /// Equality that ignores input sequence numbers, e.g. to recognise an RBF
+    /// re-signal of an otherwise identical transaction.
+    pub fn eq_ignoring_sequence(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.lock_time == other.lock_time
+            && self.outputs == other.outputs
+            && self.witnesses == other.witnesses
+            && self.inputs.len() == other.inputs.len()
+            && self.inputs.iter().zip(&other.inputs).all(|(a, b)| {
+                a.previous_output == b.previous_output && a.script_sig == b.script_sig
+            })
+    }
+
+    /// Minimal PSBT v0 (BIP 174): the unsigned transaction (scriptSigs stripped) as
+    /// the global record, a `PSBT_IN_WITNESS_UTXO` record for each input whose
+    /// matching entry in `prevouts` is a witness program or P2SH (possibly wrapped
+    /// segwit), and empty output maps. `non_witness_utxo` is never emitted, since a
+    /// bare prevout output cannot supply the full previous transaction it needs, so
+    /// inputs spending other (bare legacy) outputs get an empty map and signers
+    /// cannot use the result for them until an updater adds the previous tx.
+    pub fn to_psbt(&self, prevouts: &[TransactionOutput]) -> Vec<u8> {
+        fn push_record(psbt: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+            psbt.extend_from_slice(&CompactSize::new(key.len() as u64).to_bytes());
+            psbt.extend_from_slice(key);
+            psbt.extend_from_slice(&CompactSize::new(value.len() as u64).to_bytes());
+            psbt.extend_from_slice(value);
+        }
+
+        let mut unsigned = self.clone();
+        for input in &mut unsigned.inputs {
+            input.script_sig = Script::new(Vec::new());
+        }
+        unsigned.witnesses.clear();
+
+        let mut psbt = b"psbt\xff".to_vec();
+        push_record(&mut psbt, &[0x00], &unsigned.to_bytes());
+        psbt.push(0x00);
+
+        for i in 0..self.inputs.len() {
+            if let Some(prevout) = prevouts.get(i) {
+                let script = &prevout.script_pubkey;
+                if script.witness_version().is_some() || script.script_type() == ScriptType::P2SH {
+                    push_record(&mut psbt, &[0x01], &prevout.to_bytes());
+                }
+            }
+            psbt.push(0x00);
+        }
+
+        // One empty map per output
+        psbt.resize(psbt.len() + self.outputs.len(), 0x00);
+
+        psbt
+    }
+
+    /// Double-SHA256 of the legacy (witness-stripped) serialization, in internal
+    /// byte order.
+    pub fn txid(&self) -> Txid {
+        Txid(sha256d(&self.serialize(false)))
+    }
+
+    /// Double-SHA256 of the full serialization including witnesses. Equal to
+    /// `txid` when no input has witness data.
+    pub fn wtxid(&self) -> Txid {
+        Txid(sha256d(&self.to_bytes()))
+    }
+
+    /// Distinct outpoints this transaction spends, in input order, for fetching
+    /// prevouts from a node. The coinbase null outpoint is skipped.
+    pub fn prevout_requests(&self) -> Vec<OutPoint> {
+        let mut requests: Vec<OutPoint> = Vec::new();
+        for input in &self.inputs {
+            let outpoint = &input.previous_output;
+            if !outpoint.is_null() && !requests.contains(outpoint) {
+                requests.push(outpoint.clone());
+            }
+        }
+        requests
+    }
+
+    /// Decodes back-to-back transactions until the buffer is exhausted. Trailing
+    /// bytes that do not form a complete transaction are `InvalidFormat`.
+    pub fn from_bytes_all(bytes: &[u8]) -> Result<Vec<BitcoinTransaction>, BitcoinError> {
+        let mut txs = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let (tx, consumed) = BitcoinTransaction::from_bytes(&bytes[offset..])
+                .map_err(|_| BitcoinError::InvalidFormat)?;
+            txs.push(tx);
+            offset += consumed;
+        }
+        Ok(txs)
+    }
+
+    /// Serialization of the single output at `index`, as committed to by
+    /// SIGHASH_SINGLE, or `None` if there is no such output.
+    pub fn output_bytes_for_single(&self, index: usize) -> Option<Vec<u8>> {
+        self.outputs.get(index).map(TransactionOutput::to_bytes)
+    }
+
+    /// BIP141 weight: `base_size * 3 + total_size`, where the base size excludes
+    /// the marker, flag and witnesses.
+    pub fn weight(&self) -> usize {
+        let base_size = self.encoded_len(false);
+        let total_size = self.serialized_len();
+        base_size * 3 + total_size
+    }
+
+    /// Length of `to_bytes()`, computed without serializing into a buffer.
+    pub fn serialized_len(&self) -> usize {
+        self.encoded_len(self.has_witness())
+    }
+
+    /// Self-check that `serialized_len()`, `to_bytes().len()` and the sum of the
+    /// parts (version, counts, inputs, outputs, marker, flag, witnesses and
+    /// lock_time, each from its own `serialized_len`) all agree.
+    pub fn verify_size_consistency(&self) -> bool {
+        let count_len = |n: usize| CompactSize::new(n as u64).serialized_len();
+        let script_len = |script: &Script| count_len(script.len()) + script.len();
+
+        let mut parts = 4 + count_len(self.inputs.len()) + count_len(self.outputs.len()) + 4;
+        parts += self
+            .inputs
+            .iter()
+            .map(|input| 36 + script_len(&input.script_sig) + 4)
+            .sum::<usize>();
+        parts += self
+            .outputs
+            .iter()
+            .map(|output| 8 + script_len(&output.script_pubkey))
+            .sum::<usize>();
+        if self.has_witness() {
+            parts += 2 + self.witness_bytes().len();
+        }
+
+        let len = self.serialized_len();
+        len == self.to_bytes().len() && len == parts
+    }
+
+    /// Copy with every scriptSig and scriptPubKey replaced by zero-filled scripts
+    /// of the given lengths, for building transactions of an exact size in
+    /// fee/vsize tests. Witnesses are kept.
+    pub fn with_dummy_scripts(&self, input_sigscript_len: usize, output_script_len: usize) -> Self {
+        let mut tx = self.clone();
+        for input in &mut tx.inputs {
+            input.script_sig = Script::new(vec![0u8; input_sigscript_len]);
+        }
+        for output in &mut tx.outputs {
+            output.script_pubkey = Script::new(vec![0u8; output_script_len]);
+        }
+        tx
+    }
+
+    fn encoded_len(&self, segwit: bool) -> usize {
+        let mut counter = LenCounter(0);
+        let Ok(()) = self.encode(&mut counter, segwit);
+        counter.0
+    }
+
+    /// Virtual size in vbytes: weight / 4, rounded up.
+    pub fn vsize(&self) -> usize {
+        self.weight().div_ceil(4)
+    }
+
+    /// Bucket of `vsize()`: up to 250 vB is `Tiny`, 1,000 `Small`, 10,000
+    /// `Medium` and 100,000 (the standardness limit) `Large`; anything bigger is
+    /// `Huge`.
+    pub fn size_category(&self) -> SizeCategory {
+        let vsize = self.vsize();
+        match SIZE_CATEGORY_BOUNDS
+            .iter()
+            .position(|&bound| vsize <= bound)
+        {
+            Some(0) => SizeCategory::Tiny,
+            Some(1) => SizeCategory::Small,
+            Some(2) => SizeCategory::Medium,
+            Some(_) => SizeCategory::Large,
+            None => SizeCategory::Huge,
+        }
+    }
+
+    /// Heuristic classification for UX labels: many inputs into one output is a
+    /// consolidation, one input into many outputs a batch, and few inputs into two
+    /// outputs (payment plus change) a payment.
+    pub fn shape(&self) -> TxShape {
+        let (inputs, outputs) = (self.inputs.len(), self.outputs.len());
+        if inputs >= SHAPE_MANY && outputs == 1 {
+            TxShape::Consolidation
+        } else if inputs == 1 && outputs >= SHAPE_MANY {
+            TxShape::Batch
+        } else if (1..SHAPE_MANY).contains(&inputs) && outputs == 2 {
+            TxShape::Payment
+        } else {
+            TxShape::Unknown
+        }
+    }
+
+    /// SHA-256 over the version, input/output counts and output script types,
+    /// ignoring amounts, txids and script contents, so structurally identical
+    /// transactions (e.g. from the same wallet software) share a fingerprint.
+    pub fn structural_fingerprint(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.version.to_le_bytes());
+        hasher.update(CompactSize::new(self.inputs.len() as u64).to_bytes());
+        hasher.update(CompactSize::new(self.outputs.len() as u64).to_bytes());
+        for output in &self.outputs {
+            hasher.update([output.script_pubkey.script_type() as u8]);
+        }
+        hasher.finalize().into()
+    }
+
+    /// Whether lock_time actually constrains the transaction. As in Core's
+    /// `IsFinalTx`, a non-zero lock_time is ignored when every input has a final
+    /// (0xFFFFFFFF) sequence.
+    pub fn locktime_is_effective(&self) -> bool {
+        self.lock_time != 0 && self.inputs.iter().any(|input| input.sequence != 0xFFFFFFFF)
+    }
+
+    /// Index of the first byte where `bytes` and its decode-then-encode round trip
+    /// differ, or `None` if they are identical. Trailing bytes diverge at the end
+    /// of the decoded transaction; undecodable input diverges at 0.
+    pub fn roundtrip_divergence(bytes: &[u8]) -> Option<usize> {
+        let reencoded = match BitcoinTransaction::from_bytes(bytes) {
+            Ok((tx, _)) => tx.to_bytes(),
+            Err(_) => return Some(0),
+        };
+        if reencoded == bytes {
+            return None;
+        }
+        let common = bytes.len().min(reencoded.len());
+        Some(
+            (0..common)
+                .find(|&i| bytes[i] != reencoded[i])
+                .unwrap_or(common),
+        )
+    }
+
+    /// Field-level changes from `self` to `other` as a JSON object, e.g. for a UI
+    /// comparing an original with its replacement. Only changed fields appear:
+    /// `version` and `lock_time` map to `{"old", "new"}`, while `inputs`,
+    /// `outputs` and `witnesses` map each changed index to such a pair.
+    pub fn diff_json(&self, other: &Self) -> serde_json::Value {
+        let mut diff = serde_json::Map::new();
+        if self.version != other.version {
+            diff.insert(
+                "version".to_string(),
+                serde_json::json!({ "old": self.version, "new": other.version }),
+            );
+        }
+        if let Some(changes) = diff_list(&self.inputs, &other.inputs) {
+            diff.insert("inputs".to_string(), changes);
+        }
+        if let Some(changes) = diff_list(&self.outputs, &other.outputs) {
+            diff.insert("outputs".to_string(), changes);
+        }
+        if let Some(changes) = diff_list(&self.witnesses, &other.witnesses) {
+            diff.insert("witnesses".to_string(), changes);
+        }
+        if self.lock_time != other.lock_time {
+            diff.insert(
+                "lock_time".to_string(),
+                serde_json::json!({ "old": self.lock_time, "new": other.lock_time }),
+            );
+        }
+        serde_json::Value::Object(diff)
+    }
+
+    /// BIP69 canonical ordering. Inputs sort by previous txid compared in
+    /// displayed (byte-reversed) order, then vout, keeping each witness stack with
+    /// its input; outputs sort by value, then scriptPubKey bytes.
+    pub fn sort_bip69(&mut self) {
+        let input_key = |input: &TransactionInput| {
+            let mut txid = input.previous_output.txid.0;
+            txid.reverse();
+            (txid, input.previous_output.vout)
+        };
+
+        if self.witnesses.is_empty() {
+            self.inputs.sort_by_key(input_key);
+        } else {
+            let mut witnesses = core::mem::take(&mut self.witnesses);
+            witnesses.resize(self.inputs.len(), Vec::new());
+            let mut pairs: Vec<_> = self.inputs.drain(..).zip(witnesses).collect();
+            pairs.sort_by_key(|(input, _)| input_key(input));
+            (self.inputs, self.witnesses) = pairs.into_iter().unzip();
+        }
+
+        self.outputs.sort_by(|a, b| {
+            a.value
+                .cmp(&b.value)
+                .then_with(|| a.script_pubkey.bytes.cmp(&b.script_pubkey.bytes))
+        });
+    }
+
+    /// Fee paid given the value of each input's prevout, in input order.
+    /// `InvalidFormat` if the amounts don't line up with the inputs, overflow, or
+    /// the outputs spend more than the inputs provide.
+    pub fn fee(&self, input_amounts: &[u64]) -> Result<u64, BitcoinError> {
+        Ok(self.flow(input_amounts)?.fee)
+    }
+
+    /// Input total, output total and fee given the outputs spent by each input,
+    /// in input order. Errors as for `fee`.
+    pub fn value_flow(&self, prevouts: &[TransactionOutput]) -> Result<ValueFlow, BitcoinError> {
+        let amounts: Vec<u64> = prevouts.iter().map(|prevout| prevout.value).collect();
+        self.flow(&amounts)
+    }
+
+    fn flow(&self, input_amounts: &[u64]) -> Result<ValueFlow, BitcoinError> {
+        if input_amounts.len() != self.inputs.len() {
+            return Err(BitcoinError::InvalidFormat);
+        }
+        let total_input = input_amounts
+            .iter()
+            .try_fold(0u64, |sum, &amount| sum.checked_add(amount))
+            .ok_or(BitcoinError::InvalidFormat)?;
+        let total_output = self
+            .outputs
+            .iter()
+            .try_fold(0u64, |sum, output| sum.checked_add(output.value))
+            .ok_or(BitcoinError::InvalidFormat)?;
+        let fee = total_input
+            .checked_sub(total_output)
+            .ok_or(BitcoinError::InvalidFormat)?;
+        Ok(ValueFlow {
+            total_input,
+            total_output,
+            fee,
+        })
+    }
+
+    /// Fee in sat/vB, see `fee` for the error cases.
+    pub fn fee_rate(&self, input_amounts: &[u64]) -> Result<f64, BitcoinError> {
+        Ok(self.fee(input_amounts)? as f64 / self.vsize() as f64)
+    }
+
+    /// Serialization with every output value zeroed, for sharing a transaction's
+    /// structure without disclosing amounts.
+    pub fn to_amount_redacted_bytes(&self) -> Vec<u8> {
+        let mut redacted = self.clone();
+        for output in &mut redacted.outputs {
+            output.value = 0;
+        }
+        redacted.to_bytes()
+    }
+
+    /// The witness script of a P2WSH spend of input `index`: its last witness item.
+    pub fn p2wsh_witness_script(&self, index: usize) -> Option<Script> {
+        let witness_script = self.witnesses.get(index)?.last()?;
+        Some(Script::new(witness_script.clone()))
+    }
+
+    /// For a P2WSH `prevout`, checks that input `index`'s witness script hashes
+    /// (single SHA-256) to the committed 32-byte program.
+    pub fn p2wsh_matches(&self, index: usize, prevout: &Script) -> bool {
+        if prevout.script_type() != ScriptType::P2WSH {
+            return false;
+        }
+        self.witnesses
+            .get(index)
+            .and_then(|stack| stack.last())
+            .is_some_and(|witness_script| Sha256::digest(witness_script)[..] == prevout.bytes[2..])
+    }
+
+    /// Weight each input contributes: its serialized bytes times 4 plus, in
+    /// SegWit form, its witness stack bytes once. The version, counts, outputs,
+    /// lock_time, marker and flag are shared and not attributed to any input.
+    pub fn per_input_weight(&self) -> Vec<usize> {
+        let segwit = self.has_witness();
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                let mut base = LenCounter(0);
+                let Ok(()) = input.encode(&mut base);
+                let mut witness = LenCounter(0);
+                if segwit {
+                    let stack = self.witnesses.get(i).map(Vec::as_slice).unwrap_or(&[]);
+                    let Ok(()) = encode_witness_stack(stack, &mut witness);
+                }
+                base.0 * 4 + witness.0
+            })
+            .collect()
+    }
+
+    /// Number of inputs with and without witness items, in that order.
+    pub fn input_witness_summary(&self) -> (usize, usize) {
+        let with_witness = (0..self.inputs.len())
+            .filter(|&i| self.witnesses.get(i).is_some_and(|stack| !stack.is_empty()))
+            .count();
+        (with_witness, self.inputs.len() - with_witness)
+    }
+
+    /// Whether input `index`'s witness ends in a BIP341 annex: it has at least two
+    /// items and the last starts with 0x50. A lone item starting with 0x50 is not
+    /// an annex.
+    pub fn has_annex(&self, index: usize) -> bool {
+        self.witnesses
+            .get(index)
+            .is_some_and(|stack| split_annex(stack).1.is_some())
+    }
+
+    /// The Schnorr signature of a Taproot key-path spend of input `index`: the only
+    /// witness item once any annex is removed, if it is 64 or 65 bytes (the latter
+    /// with an explicit sighash type) and the scriptSig is empty.
+    pub fn taproot_key_spend_signature(&self, index: usize) -> Option<&[u8]> {
+        if !self.inputs.get(index)?.script_sig.is_empty() {
+            return None;
+        }
+        match split_annex(self.witnesses.get(index)?).0 {
+            [sig] if matches!(sig.len(), 64 | 65) => Some(sig),
+            _ => None,
+        }
+    }
+
+    // Prevout type guessed from how input `index` is spent (scriptSig and witness shape)
+    fn spent_script_type(&self, index: usize) -> Option<ScriptType> {
+        let input = self.inputs.get(index)?;
+        let witness = self.witnesses.get(index).map(Vec::as_slice).unwrap_or(&[]);
+        if input.script_sig.is_empty() {
+            return match witness {
+                [_sig, pubkey] if pubkey.len() == COMPRESSED_PUBKEY_LEN => Some(ScriptType::P2WPKH),
+                [_sig] => Some(ScriptType::P2TR),
+                _ => None,
+            };
+        }
+        if input.nested_segwit_type().is_some() {
+            return Some(ScriptType::P2SH);
+        }
+        match input.script_sig.parse_ops().ok()?.as_slice() {
+            [(_, Some(_sig)), (_, Some(pubkey))] if matches!(pubkey.len(), 33 | 65) => {
+                Some(ScriptType::P2PKH)
+            }
+            _ => None,
+        }
+    }
+
+    /// Heuristic guess at the change output, for privacy analysis; wallets can and
+    /// do defeat it. Prefers the one output whose value is least round (fewest
+    /// trailing decimal zeros); failing that, the one output whose script type
+    /// matches the type every input appears to spend.
+    pub fn likely_change_output(&self) -> Option<u32> {
+        let candidates: Vec<(usize, &TransactionOutput)> = self
+            .outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| output.script_pubkey.script_type() != ScriptType::OpReturn)
+            .collect();
+        if candidates.len() < 2 {
+            return None;
+        }
+
+        let roundness = |value: u64| {
+            let mut zeros = 0;
+            let mut v = value;
+            while v != 0 && v.is_multiple_of(10) {
+                zeros += 1;
+                v /= 10;
+            }
+            zeros
+        };
+        let least_round = candidates
+            .iter()
+            .map(|(_, output)| roundness(output.value))
+            .min()?;
+        let mut least_round_outputs = candidates
+            .iter()
+            .filter(|(_, output)| roundness(output.value) == least_round);
+        if let (Some((index, _)), None) = (least_round_outputs.next(), least_round_outputs.next()) {
+            return Some(*index as u32);
+        }
+
+        let input_type = self.spent_script_type(0)?;
+        if (1..self.inputs.len()).any(|i| self.spent_script_type(i) != Some(input_type)) {
+            return None;
+        }
+        let mut matching = candidates
+            .iter()
+            .filter(|(_, output)| output.script_pubkey.script_type() == input_type);
+        match (matching.next(), matching.next()) {
+            (Some((index, _)), None) => Some(*index as u32),
+            _ => None,
+        }
+    }
+
+    /// One-shot payment spending all of `utxos` (empty scriptSigs, RBF-signalling
+    /// sequence 0xFFFFFFFD) to `recipient`, with the remainder after `fee` sent to
+    /// `change` unless it would be dust. `InvalidFormat` if the UTXOs don't cover
+    /// `amount + fee`.
+    pub fn simple_send(
+        utxos: &[(OutPoint, u64)],
+        recipient: Script,
+        amount: u64,
+        change: Script,
+        fee: u64,
+    ) -> Result<BitcoinTransaction, BitcoinError> {
+        let total_in = utxos
+            .iter()
+            .try_fold(0u64, |sum, (_, value)| sum.checked_add(*value))
+            .ok_or(BitcoinError::InvalidFormat)?;
+        let remainder = amount
+            .checked_add(fee)
+            .and_then(|spent| total_in.checked_sub(spent))
+            .ok_or(BitcoinError::InvalidFormat)?;
+
+        let mut builder = utxos
+            .iter()
+            .fold(TransactionBuilder::new(), |builder, (outpoint, _)| {
+                builder.add_input(outpoint.clone(), Script::new(Vec::new()), 0xFFFFFFFD)
+            })
+            .add_output(amount, recipient);
+        if remainder >= change.dust_threshold() {
+            builder = builder.add_output(remainder, change);
+        }
+        Ok(builder.build())
+    }
+
+    /// RBF fee bump: a copy paying `additional_fee` more, taken out of the output
+    /// at `change_output_index`. `InvalidFormat` if there is no such output or its
+    /// new value would be below its dust threshold. Signatures are not updated.
+    pub fn bump_fee(
+        &self,
+        additional_fee: u64,
+        change_output_index: usize,
+    ) -> Result<BitcoinTransaction, BitcoinError> {
+        let mut bumped = self.clone();
+        let change = bumped
+            .outputs
+            .get_mut(change_output_index)
+            .ok_or(BitcoinError::InvalidFormat)?;
+        change.value = change
+            .value
+            .checked_sub(additional_fee)
+            .filter(|&value| value >= change.script_pubkey.dust_threshold())
+            .ok_or(BitcoinError::InvalidFormat)?;
+        Ok(bumped)
+    }
+
+    /// A coinbase has exactly one input, spending the null outpoint (zero txid,
+    /// vout 0xFFFFFFFF).
+    pub fn is_coinbase(&self) -> bool {
+        matches!(self.inputs.as_slice(), [input] if input.previous_output.is_null())
+    }
+
+    /// Number of inputs whose prevout scriptPubKey was already spent by an earlier
+    /// input, i.e. inputs minus distinct scripts; `prevouts` are in input order.
+    /// Address reuse links the inputs to one owner, lowering privacy.
+    pub fn input_address_reuse(&self, prevouts: &[TransactionOutput]) -> usize {
+        let mut seen: Vec<&Script> = Vec::new();
+        let mut reused = 0;
+        for prevout in prevouts.iter().take(self.inputs.len()) {
+            if seen.contains(&&prevout.script_pubkey) {
+                reused += 1;
+            } else {
+                seen.push(&prevout.script_pubkey);
+            }
+        }
+        reused
+    }
+
+    /// BIP143 `hashOutputs`: double SHA-256 of the serialized outputs, concatenated
+    /// without the output-count prefix.
+    pub fn outputs_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for output in &self.outputs {
+            let Ok(()) = output.encode(&mut hasher);
+        }
+        Sha256::digest(hasher.finalize()).into()
+    }
+
+    /// BIP141 sigop cost of the scriptSigs and scriptPubKeys: the legacy
+    /// `Script::sigop_count` of each, scaled by the witness factor of 4. P2SH
+    /// redeem scripts and witness scripts are not counted.
+    pub fn sigops_cost(&self) -> usize {
+        let script_sigs = self.inputs.iter().map(|input| &input.script_sig);
+        let script_pubkeys = self.outputs.iter().map(|output| &output.script_pubkey);
+        script_sigs
+            .chain(script_pubkeys)
+            .map(Script::sigop_count)
+            .sum::<usize>()
+            * 4
+    }
+
+    /// The per-input witness stacks exactly as `to_bytes` writes them between
+    /// the last output and the lock_time in SegWit form (empty if there are no
+    /// inputs), for comparing witness data byte for byte.
+    pub fn witness_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        let Ok(()) = self.encode_witnesses(&mut result);
+        result
+    }
+
+    /// Witness-stripped legacy serialization, i.e. the txid preimage, so
+    /// transactions differing only in witnesses have equal canonical bytes.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        self.serialize(false)
+    }
+
+    /// Every data push in the scriptSigs, then the scriptPubKeys, then every
+    /// witness item, in transaction order. Scripts with a cut-off push contribute
+    /// nothing; pushes inside a witness script are not unpacked.
+    pub fn all_pushed_data(&self) -> Vec<Vec<u8>> {
+        let script_sigs = self.inputs.iter().map(|input| &input.script_sig);
+        let script_pubkeys = self.outputs.iter().map(|output| &output.script_pubkey);
+        let mut data: Vec<Vec<u8>> = script_sigs
+            .chain(script_pubkeys)
+            .filter_map(|script| script.parse_ops().ok())
+            .flatten()
+            .filter_map(|(_, push)| push.map(<[u8]>::to_vec))
+            .collect();
+        data.extend(self.witnesses.iter().flatten().cloned());
+        data
+    }
+
+    /// Indices of the outputs paying one of `owned_scripts`, i.e. change and
+    /// self-sends from the owning wallet's point of view.
+    pub fn self_transfer_outputs(&self, owned_scripts: &[Script]) -> Vec<u32> {
+        (0u32..)
+            .zip(&self.outputs)
+            .filter(|(_, output)| owned_scripts.contains(&output.script_pubkey))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// `to_bytes`, refused with `InvalidFormat` when `value_flow(prevouts)` fails,
+    /// e.g. because the outputs spend more than the prevouts provide.
+    pub fn to_bytes_validated(
+        &self,
+        prevouts: &[TransactionOutput],
+    ) -> Result<Vec<u8>, BitcoinError> {
+        self.value_flow(prevouts)?;
+        Ok(self.to_bytes())
+    }
+
+    /// Index and payload of the first OP_RETURN output. The payload is the data
+    /// of the pushes after OP_RETURN, concatenated, or the raw bytes after it if
+    /// they don't parse as pushes.
+    pub fn first_op_return(&self) -> Option<(u32, Vec<u8>)> {
+        let (index, output) = (0u32..)
+            .zip(&self.outputs)
+            .find(|(_, output)| output.script_pubkey.script_type() == ScriptType::OpReturn)?;
+        let tail = Script::new(output.script_pubkey.bytes[1..].to_vec());
+        let payload = match tail.parse_ops() {
+            Ok(ops) if ops.iter().all(|(_, data)| data.is_some()) => ops
+                .iter()
+                .filter_map(|(_, data)| *data)
+                .flatten()
+                .copied()
+                .collect(),
+            _ => tail.bytes.clone(),
+        };
+        Some((index, payload))
+    }
+}
+
+/// Virtual bytes saved by paying in one batched transaction instead of the
+/// `individual` ones; negative if the batch is larger.
+pub fn batching_savings(individual: &[BitcoinTransaction], batched: &BitcoinTransaction) -> i64 {
+    let separate: usize = individual.iter().map(BitcoinTransaction::vsize).sum();
+    separate as i64 - batched.vsize() as i64
+}
+
+// Field boundaries of a serialized transaction, found without decoding it
+struct RawLayout {
+    inputs_start: usize,
+    outputs_end: usize,
+    has_witness: bool,
+    len: usize,
+}
+
+fn raw_layout(bytes: &[u8], segwit: bool) -> Result<RawLayout, BitcoinError> {
+    // Advances past `n` bytes at `offset`, or reports that they are missing
+    fn skip(bytes: &[u8], offset: usize, n: usize) -> Result<usize, BitcoinError> {
+        if bytes.len() - offset < n {
+            return Err(BitcoinError::InsufficientBytes { offset, needed: n });
+        }
+        Ok(offset + n)
+    }
+    fn count(bytes: &[u8], offset: &mut usize) -> Result<u64, BitcoinError> {
+        let (value, len) =
+            CompactSize::from_bytes(&bytes[*offset..]).map_err(|e| e.shifted(*offset))?;
+        *offset += len;
+        Ok(value.value)
+    }
+
+    let inputs_start = skip(bytes, 0, if segwit { 6 } else { 4 })?;
+    let mut offset = inputs_start;
+
+    let input_count = count(bytes, &mut offset)?;
+    for _ in 0..input_count {
+        offset = skip(bytes, offset, 36)?;
+        let (_, script_len) = split_script(&bytes[offset..]).map_err(|e| e.shifted(offset))?;
+        offset = skip(bytes, offset + script_len, 4)?;
+    }
+
+    for _ in 0..count(bytes, &mut offset)? {
+        offset = skip(bytes, offset, 8)?;
+        let (_, script_len) = split_script(&bytes[offset..]).map_err(|e| e.shifted(offset))?;
+        offset += script_len;
+    }
+    let outputs_end = offset;
+
+    let mut has_witness = false;
+    if segwit {
+        for _ in 0..input_count {
+            let item_count = count(bytes, &mut offset)?;
+            has_witness |= item_count > 0;
+            for _ in 0..item_count {
+                let item_len = count(bytes, &mut offset)?;
+                offset = skip(
+                    bytes,
+                    offset,
+                    usize::try_from(item_len).unwrap_or(usize::MAX),
+                )?;
+            }
+        }
+    }
+
+    Ok(RawLayout {
+        inputs_start,
+        outputs_end,
+        has_witness,
+        len: skip(bytes, offset, 4)?,
+    })
+}
+
+/// Txid of the transaction at the start of `bytes` and its full length, found
+/// by walking the field boundaries and hashing the legacy parts in place, with
+/// no `BitcoinTransaction` built (e.g. for indexing a block body). The marker
+/// and flag are handled as in `BitcoinTransaction::from_bytes`, except that its
+/// whole-buffer legacy fallback does not apply, since more transactions may
+/// follow.
+pub fn txid_of_raw(bytes: &[u8]) -> Result<(Txid, usize), BitcoinError> {
+    let mut layout = raw_layout(bytes, false);
+    if bytes.len() >= 6 && bytes[4] == 0x00 && bytes[5] == 0x01 {
+        let segwit = raw_layout(bytes, true)?;
+        layout = if segwit.has_witness {
+            Ok(segwit)
+        } else {
+            layout.map_err(|_| BitcoinError::InvalidFormat)
+        };
+    }
+    let layout = layout?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(&bytes[..4]);
+    hasher.update(&bytes[layout.inputs_start..layout.outputs_end]);
+    hasher.update(&bytes[layout.len - 4..layout.len]);
+    Ok((Txid(Sha256::digest(hasher.finalize()).into()), layout.len))
+}
+
+/// Chainable constructor for `BitcoinTransaction`. Defaults to version 2 and
+/// lock_time 0.
+#[derive(Debug, Clone)]
+pub struct TransactionBuilder {
+    version: u32,
+    inputs: Vec<TransactionInput>,
+    outputs: Vec<TransactionOutput>,
+    lock_time: u32,
+}
+
+impl Default for TransactionBuilder {
+    fn default() -> Self {
+        TransactionBuilder::new()
+    }
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        TransactionBuilder {
+            version: 2,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            lock_time: 0,
+        }
+    }
+
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn lock_time(mut self, lock_time: u32) -> Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    pub fn add_input(
+        mut self,
+        previous_output: OutPoint,
+        script_sig: Script,
+        sequence: u32,
+    ) -> Self {
+        self.inputs
+            .push(TransactionInput::new(previous_output, script_sig, sequence));
+        self
+    }
+
+    pub fn add_output(mut self, value: u64, script_pubkey: Script) -> Self {
+        self.outputs
+            .push(TransactionOutput::new(value, script_pubkey));
+        self
+    }
+
+    pub fn build(self) -> BitcoinTransaction {
+        BitcoinTransaction::new(self.version, self.inputs, self.outputs, self.lock_time)
+    }
+}
+
+// Accept a `from_bytes` result only if it consumed the whole buffer
+fn require_exact<T>(bytes: &[u8], (value, consumed): (T, usize)) -> Result<T, BitcoinError> {
+    if consumed != bytes.len() {
+        return Err(BitcoinError::InvalidFormat);
+    }
+    Ok(value)
+}
+
+// `TryFrom` decodes exactly one value: trailing bytes are `InvalidFormat`
+
+impl TryFrom<&[u8]> for CompactSize {
+    type Error = BitcoinError;
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        require_exact(bytes, CompactSize::from_bytes(bytes)?)
+    }
+}
+
+impl TryFrom<&[u8]> for OutPoint {
+    type Error = BitcoinError;
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        require_exact(bytes, OutPoint::from_bytes(bytes)?)
+    }
+}
+
+impl TryFrom<&[u8]> for Script {
+    type Error = BitcoinError;
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        require_exact(bytes, Script::from_bytes(bytes)?)
+    }
+}
+
+impl TryFrom<&[u8]> for TransactionInput {
+    type Error = BitcoinError;
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        require_exact(bytes, TransactionInput::from_bytes(bytes)?)
+    }
+}
+
+impl TryFrom<&[u8]> for BitcoinTransaction {
+    type Error = BitcoinError;
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        BitcoinTransaction::from_bytes_exact(bytes)
+    }
 }
 
 impl fmt::Display for BitcoinTransaction {
@@ -1,9 +1,50 @@
 use rust_week_3_exercises::*;
+use std::io::{Cursor, Read};
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Mainnet tx f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16
+    // (block 170, the first bitcoin transfer)
+    const BLOCK_170_TX: &str = concat!(
+        "0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704",
+        "000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548",
+        "ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d",
+        "1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f715",
+        "9b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded",
+        "5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb",
+        "68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c",
+        "03f999b8643f656b412a3ac00000000"
+    );
+
+    // Signed native P2WPKH example from BIP 143: input 0 is P2PK, input 1 is P2WPKH
+    const BIP143_P2WPKH_TX: &str = concat!(
+        "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f",
+        "00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e",
+        "5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01",
+        "eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a010000",
+        "0000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d59",
+        "88ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247",
+        "304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a022057",
+        "3a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e831",
+        "88368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000"
+    );
+
+    // Hands out at most one byte per read call
+    struct ByteAtATime<'a>(&'a [u8]);
+
+    impl Read for ByteAtATime<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
     fn dummy_txid(val: u8) -> [u8; 32] {
         let mut txid = [0u8; 32];
         txid[31] = val;
@@ -73,7 +114,7 @@ mod tests {
             Script::new(vec![0x01, 0x02]),
             0xFFFFFFFF,
         )];
-        let tx = BitcoinTransaction::new(2, inputs.clone(), 1000);
+        let tx = BitcoinTransaction::new(2, inputs.clone(), vec![], 1000);
         let bytes = tx.to_bytes();
         let (parsed, consumed) = BitcoinTransaction::from_bytes(&bytes).unwrap();
         assert_eq!(parsed, tx);
@@ -87,7 +128,7 @@ mod tests {
             Script::new(vec![0xDE, 0xAD, 0xBE, 0xEF]),
             0xABCDEF01,
         );
-        let tx = BitcoinTransaction::new(1, vec![input], 999);
+        let tx = BitcoinTransaction::new(1, vec![input], vec![], 999);
 
         let json = serde_json::to_string_pretty(&tx).unwrap();
         let parsed: BitcoinTransaction = serde_json::from_str(&json).unwrap();
@@ -104,10 +145,2422 @@ mod tests {
             Script::new(vec![0x01, 0x02, 0x03]),
             0xFFFFFFFF,
         );
-        let tx = BitcoinTransaction::new(1, vec![input], 0);
+        let tx = BitcoinTransaction::new(1, vec![input], vec![], 0);
         let output = format!("{}", tx);
         assert!(output.contains("Version: 1"));
         assert!(output.contains("Lock Time: 0"));
         assert!(output.contains("Previous Output Vout: 7"));
     }
+
+    #[test]
+    fn test_script_truncated_prefix_vs_body() {
+        // 0xFD needs two more length bytes, only one is present
+        assert_eq!(
+            Script::from_bytes(&[0xFD, 0x01]),
+            Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 3
+            })
+        );
+        assert_eq!(
+            Script::from_bytes(&[]),
+            Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 1
+            })
+        );
+
+        // Prefix declares 5 bytes but only 2 follow
+        assert_eq!(
+            Script::from_bytes(&[0x05, 0xAA, 0xBB]),
+            Err(BitcoinError::TruncatedScript {
+                offset: 0,
+                needed: 6
+            })
+        );
+        // Absurd declared length must not overflow
+        let mut huge = vec![0xFF];
+        huge.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            Script::from_bytes(&huge),
+            Err(BitcoinError::TruncatedScript {
+                offset: 0,
+                needed: usize::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn test_input_segwit_version() {
+        let input = TransactionInput::new(OutPoint::new(dummy_txid(1), 0), Script::new(vec![]), 0);
+
+        let mut p2wpkh = vec![0x00, 0x14];
+        p2wpkh.extend_from_slice(&[0x11; 20]);
+        let mut p2wsh = vec![0x00, 0x20];
+        p2wsh.extend_from_slice(&[0x22; 32]);
+        let mut p2tr = vec![0x51, 0x20];
+        p2tr.extend_from_slice(&[0x33; 32]);
+
+        assert_eq!(input.segwit_version(&Script::new(p2wpkh.clone())), Some(0));
+        assert_eq!(input.segwit_version(&Script::new(p2wsh)), Some(0));
+        assert_eq!(input.segwit_version(&Script::new(p2tr)), Some(1));
+
+        // P2PKH prevout is not a witness program
+        let mut p2pkh = vec![0x76, 0xA9, 0x14];
+        p2pkh.extend_from_slice(&[0x44; 20]);
+        p2pkh.extend_from_slice(&[0x88, 0xAC]);
+        assert_eq!(input.segwit_version(&Script::new(p2pkh)), None);
+
+        // A scriptSig rules out a native witness spend
+        let legacy = TransactionInput::new(
+            OutPoint::new(dummy_txid(1), 0),
+            Script::new(vec![0x01, 0x02]),
+            0,
+        );
+        assert_eq!(legacy.segwit_version(&Script::new(p2wpkh)), None);
+    }
+
+    #[test]
+    fn test_consensus_cache_roundtrip() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x42), 1),
+            Script::new(vec![0xAB; 10]),
+            0xFFFFFFFE,
+        );
+        let tx = BitcoinTransaction::new(2, vec![input], vec![], 500_000);
+
+        let cached = tx.to_consensus_cached();
+        assert!(cached.len() < serde_json::to_vec(&tx).unwrap().len());
+        assert_eq!(
+            BitcoinTransaction::from_consensus_cached(&cached).unwrap(),
+            tx
+        );
+
+        let mut padded = cached.clone();
+        padded.push(0x00);
+        assert_eq!(
+            BitcoinTransaction::from_consensus_cached(&padded),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_decode_from_cursor_advances() {
+        let first = BitcoinTransaction::new(
+            1,
+            vec![TransactionInput::new(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(vec![0x51]),
+                0xFFFFFFFF,
+            )],
+            vec![],
+            0,
+        );
+        let second = BitcoinTransaction::new(
+            2,
+            vec![TransactionInput::new(
+                OutPoint::new(dummy_txid(2), 5),
+                Script::new(vec![0x01, 0x02, 0x03]),
+                0xFFFFFFFD,
+            )],
+            vec![],
+            800_000,
+        );
+
+        let mut buf = first.to_bytes();
+        buf.extend_from_slice(&second.to_bytes());
+
+        let mut cursor = Cursor::new(&buf[..]);
+        assert_eq!(
+            BitcoinTransaction::decode_from_cursor(&mut cursor).unwrap(),
+            first
+        );
+        assert_eq!(cursor.position() as usize, first.to_bytes().len());
+        assert_eq!(
+            BitcoinTransaction::decode_from_cursor(&mut cursor).unwrap(),
+            second
+        );
+        assert_eq!(cursor.position() as usize, buf.len());
+        assert!(BitcoinTransaction::decode_from_cursor(&mut cursor).is_err());
+    }
+
+    #[test]
+    fn test_txid_to_vec_is_internal_order() {
+        let txid = Txid(dummy_txid(0xAB));
+        let internal = txid.to_vec();
+        assert_eq!(internal[31], 0xAB);
+        assert_eq!(internal, txid.0.to_vec());
+
+        // Serialize reverses, so the display hex starts with the last internal byte
+        let json = serde_json::to_string(&txid).unwrap();
+        assert!(json.starts_with("\"ab00"));
+    }
+
+    #[test]
+    fn test_to_test_vector_hex_roundtrips() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x07), 3),
+            Script::new(vec![0x00, 0x14]),
+            0xFFFFFFFD,
+        );
+        let tx = BitcoinTransaction::new(2, vec![input], vec![], 42);
+        let snippet = tx.to_test_vector();
+
+        let start = snippet.find("decode(\"").unwrap() + "decode(\"".len();
+        let end = start + snippet[start..].find('"').unwrap();
+        let raw = hex::decode(&snippet[start..end]).unwrap();
+        let (parsed, _) = BitcoinTransaction::from_bytes(&raw).unwrap();
+        assert_eq!(parsed, tx);
+
+        assert!(snippet.contains("assert_eq!(tx.version, 2);"));
+        assert!(snippet.contains("assert_eq!(tx.lock_time, 42);"));
+        assert!(snippet.contains("assert_eq!(tx.inputs[0].sequence, 0xfffffffd);"));
+    }
+
+    #[test]
+    fn test_length_prefixed_roundtrip() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(9), 1),
+            Script::new(vec![0x6A, 0x01, 0xFF]),
+            0xFFFFFFFF,
+        );
+        let tx = BitcoinTransaction::new(1, vec![input], vec![], 0);
+        let bytes = tx.to_length_prefixed();
+        assert_eq!(bytes[0] as usize, tx.to_bytes().len());
+
+        let (parsed, consumed) = BitcoinTransaction::from_length_prefixed(&bytes).unwrap();
+        assert_eq!(parsed, tx);
+        assert_eq!(consumed, bytes.len());
+
+        // Prefix claims one byte more than the transaction actually uses
+        let mut padded = vec![bytes[0] + 1];
+        padded.extend_from_slice(&bytes[1..]);
+        padded.push(0x00);
+        assert_eq!(
+            BitcoinTransaction::from_length_prefixed(&padded),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_network_parameters() {
+        assert_eq!(Network::Mainnet.bech32_hrp(), "bc");
+        assert_eq!(Network::Testnet.bech32_hrp(), "tb");
+        assert_eq!(Network::Signet.bech32_hrp(), "tb");
+        assert_eq!(Network::Regtest.bech32_hrp(), "bcrt");
+
+        assert_eq!(Network::Mainnet.p2pkh_version(), 0x00);
+        assert_eq!(Network::Mainnet.p2sh_version(), 0x05);
+        for network in [Network::Testnet, Network::Signet, Network::Regtest] {
+            assert_eq!(network.p2pkh_version(), 0x6F);
+            assert_eq!(network.p2sh_version(), 0xC4);
+        }
+    }
+
+    #[test]
+    fn test_p2sh_matches_redeem_script() {
+        // P2SH of the redeem script OP_TRUE
+        let mut spk = vec![0xA9, 0x14];
+        spk.extend_from_slice(&hex::decode("da1745e9b549bd0bfa1a569971c77eba30cd5a4b").unwrap());
+        spk.push(0x87);
+        let p2sh = Script::new(spk);
+
+        assert!(p2sh.p2sh_matches(&Script::new(vec![0x01, 0x51])));
+        // Extra leading pushes are fine, the redeem script is the last one
+        assert!(p2sh.p2sh_matches(&Script::new(vec![0x00, 0x02, 0xAA, 0xBB, 0x01, 0x51])));
+
+        // Different redeem script (OP_2)
+        assert!(!p2sh.p2sh_matches(&Script::new(vec![0x01, 0x52])));
+        // Not push-only
+        assert!(!p2sh.p2sh_matches(&Script::new(vec![0x01, 0x51, 0x75])));
+        // Truncated push
+        assert!(!p2sh.p2sh_matches(&Script::new(vec![0x05, 0x51])));
+        // Not a P2SH scriptPubKey
+        assert!(!Script::new(vec![0x51]).p2sh_matches(&Script::new(vec![0x01, 0x51])));
+    }
+
+    #[test]
+    fn test_short_txid_siphash_vector() {
+        // SipHash-2-4 reference vector: key 00..0f, message 00..1f
+        // gives 0x7127512f72f27cce; BIP 152 keeps the low 48 bits.
+        let mut bytes = [0u8; 32];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let txid = Txid(bytes);
+        let k0 = 0x0706050403020100;
+        let k1 = 0x0f0e0d0c0b0a0908;
+        assert_eq!(short_txid(&txid, k0, k1), 0x512f72f27cce);
+        assert!(short_txid(&Txid(dummy_txid(1)), 1, 2) < 1 << 48);
+    }
+
+    #[test]
+    fn test_outpoint_from_bytes_lengths() {
+        // Genesis coinbase txid as displayed by explorers
+        let display = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+        let mut wire = hex::decode(display).unwrap();
+        wire.reverse();
+        wire.extend_from_slice(&7u32.to_le_bytes());
+        assert_eq!(wire.len(), 36);
+
+        assert_eq!(
+            OutPoint::from_bytes(&wire[..35]),
+            Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 36
+            })
+        );
+
+        let (outpoint, consumed) = OutPoint::from_bytes(&wire).unwrap();
+        assert_eq!(consumed, 36);
+        assert_eq!(outpoint.vout, 7);
+        assert_eq!(
+            serde_json::to_string(&outpoint.txid).unwrap(),
+            format!("\"{}\"", display)
+        );
+        assert_eq!(outpoint.to_bytes(), wire);
+
+        // Trailing bytes are left for the caller
+        let mut longer = wire.clone();
+        longer.extend_from_slice(&[0xDE, 0xAD]);
+        let (parsed, consumed) = OutPoint::from_bytes(&longer).unwrap();
+        assert_eq!(parsed, outpoint);
+        assert_eq!(consumed, 36);
+    }
+
+    #[test]
+    fn test_script_from_address() {
+        let cases = [
+            (
+                "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+                Network::Mainnet,
+                "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac",
+            ),
+            (
+                "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy",
+                Network::Mainnet,
+                "a914b472a266d0bd89c13706a4132ccfb16f7c3b9fcb87",
+            ),
+            (
+                "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn",
+                Network::Testnet,
+                "76a914243f1394f44554f4ce3fd68649c19adc483ce92488ac",
+            ),
+            (
+                "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc",
+                Network::Testnet,
+                "a9144e9f39ca4688ff102128ea4ccda34105324305b087",
+            ),
+            (
+                "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+                Network::Mainnet,
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            ),
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                Network::Testnet,
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                Network::Mainnet,
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            ),
+        ];
+        for (address, network, spk) in cases {
+            let script = Script::from_address(address, network).unwrap();
+            assert_eq!(hex::encode(&script.bytes), spk, "{}", address);
+        }
+
+        // Wrong network
+        assert!(
+            Script::from_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", Network::Testnet).is_err()
+        );
+        assert!(
+            Script::from_address(
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                Network::Testnet
+            )
+            .is_err()
+        );
+        // Bad checksums
+        assert!(
+            Script::from_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb", Network::Mainnet).is_err()
+        );
+        assert!(
+            Script::from_address(
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5",
+                Network::Mainnet
+            )
+            .is_err()
+        );
+        // A v1 program encoded with the bech32 (not bech32m) checksum
+        assert!(
+            Script::from_address(
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7k7grplx",
+                Network::Mainnet
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_compact_size_read_from_stream() {
+        // 0xFE-prefixed value followed by a byte that belongs to the next field
+        let data = [0xFE, 0x00, 0x00, 0x01, 0x00, 0x2A];
+        let mut reader = ByteAtATime(&data);
+        let cs = CompactSize::read_from(&mut reader).unwrap();
+        assert_eq!(cs.value, 65536);
+        assert_eq!(reader.0, &[0x2A]);
+
+        let mut reader = ByteAtATime(&[0xFC]);
+        assert_eq!(CompactSize::read_from(&mut reader).unwrap().value, 252);
+
+        let mut reader = ByteAtATime(&[0xFF, 0x01, 0x02]);
+        assert_eq!(
+            CompactSize::read_from(&mut reader),
+            Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 9
+            })
+        );
+    }
+
+    #[test]
+    fn test_spends_outpoint_and_coinbase_inputs() {
+        let inputs = vec![
+            TransactionInput::new(OutPoint::new(dummy_txid(1), 0), Script::new(vec![]), 0),
+            TransactionInput::new(OutPoint::new(dummy_txid(2), 1), Script::new(vec![]), 0),
+            TransactionInput::new(OutPoint::new(dummy_txid(3), 0), Script::new(vec![]), 0),
+        ];
+        let tx = BitcoinTransaction::new(2, inputs, vec![], 0);
+
+        assert!(tx.spends_outpoint(&OutPoint::new(dummy_txid(2), 1)));
+        assert!(!tx.spends_outpoint(&OutPoint::new(dummy_txid(2), 0)));
+
+        let coinbase_txid = Txid(dummy_txid(2));
+        assert_eq!(tx.coinbase_inputs(|txid| *txid == coinbase_txid), vec![1]);
+        assert!(tx.coinbase_inputs(|_| false).is_empty());
+    }
+
+    #[test]
+    fn test_descendant_limit_risk() {
+        let tx = BitcoinTransaction::new(2, vec![], vec![], 0);
+        assert!(!tx.descendant_limit_risk(24, 100_999));
+        assert!(tx.descendant_limit_risk(25, 1_000));
+        assert!(tx.descendant_limit_risk(3, 101_000));
+    }
+
+    #[test]
+    fn test_compare_output_sizes() {
+        let sizes = compare_output_sizes(&[0x5A; 20]);
+        assert_eq!(
+            sizes,
+            ScriptSizeComparison {
+                p2pkh: 25,
+                p2sh: 23,
+                p2wpkh: 22,
+            }
+        );
+    }
+
+    #[test]
+    fn test_eq_ignoring_sequence() {
+        let make = |sequence: u32, vout: u32| {
+            BitcoinTransaction::new(
+                2,
+                vec![TransactionInput::new(
+                    OutPoint::new(dummy_txid(4), vout),
+                    Script::new(vec![0x01, 0x02]),
+                    sequence,
+                )],
+                vec![],
+                0,
+            )
+        };
+        let final_seq = make(0xFFFFFFFF, 0);
+        let rbf = make(0xFFFFFFFD, 0);
+        assert_ne!(final_seq, rbf);
+        assert!(final_seq.eq_ignoring_sequence(&rbf));
+        assert!(!final_seq.eq_ignoring_sequence(&make(0xFFFFFFFF, 1)));
+    }
+
+    #[test]
+    fn test_tx_output_roundtrip() {
+        let output = TransactionOutput::new(
+            50_000,
+            Script::new(vec![0x00, 0x14, 0xAA, 0xBB, 0xCC, 0xDD]),
+        );
+        let bytes = output.to_bytes();
+        assert_eq!(&bytes[..8], &50_000u64.to_le_bytes());
+        assert_eq!(bytes[8], 6);
+
+        let (parsed, consumed) = TransactionOutput::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, output);
+        assert_eq!(consumed, bytes.len());
+
+        assert_eq!(
+            TransactionOutput::from_bytes(&bytes[..7]),
+            Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_bitcoin_tx_with_outputs_roundtrip() {
+        let raw = hex::decode(BLOCK_170_TX).unwrap();
+        let (tx, consumed) = BitcoinTransaction::from_bytes(&raw).unwrap();
+        assert_eq!(consumed, raw.len());
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[0].value, 1_000_000_000);
+        assert_eq!(tx.outputs[1].value, 4_000_000_000);
+        assert_eq!(tx.to_bytes(), raw);
+    }
+
+    #[test]
+    fn test_to_psbt_layout() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(vec![0x01, 0x02, 0x03]),
+            0xFFFFFFFD,
+        );
+        let output = TransactionOutput::new(90_000, Script::p2wpkh(&[0x22; 20]));
+        let tx = BitcoinTransaction::new(2, vec![input], vec![output], 0);
+        let prevout = TransactionOutput::new(100_000, Script::p2wpkh(&[0x33; 20]));
+
+        let psbt = tx.to_psbt(std::slice::from_ref(&prevout));
+        assert_eq!(&psbt[..5], b"psbt\xff");
+
+        // Global unsigned tx record
+        assert_eq!(&psbt[5..7], &[0x01, 0x00]);
+        let (tx_len, prefix_len) = CompactSize::from_bytes(&psbt[7..]).unwrap();
+        let tx_start = 7 + prefix_len;
+        let tx_end = tx_start + tx_len.value as usize;
+        let (unsigned, _) = BitcoinTransaction::from_bytes(&psbt[tx_start..tx_end]).unwrap();
+        assert!(unsigned.inputs[0].script_sig.is_empty());
+        assert_eq!(unsigned.outputs, tx.outputs);
+        assert_eq!(psbt[tx_end], 0x00);
+
+        // Input map with the witness UTXO, then an empty output map
+        let mut expected = vec![0x01, 0x01];
+        let prevout_bytes = prevout.to_bytes();
+        expected.push(prevout_bytes.len() as u8);
+        expected.extend_from_slice(&prevout_bytes);
+        expected.push(0x00);
+        expected.push(0x00);
+        assert_eq!(&psbt[tx_end + 1..], &expected[..]);
+
+        // BIP 174 only allows witness_utxo for segwit spends: a P2PKH prevout gets
+        // an empty input map, since non_witness_utxo is not supported
+        let legacy_prevout = TransactionOutput::new(100_000, Script::p2pkh(&[0x33; 20]));
+        let psbt = tx.to_psbt(std::slice::from_ref(&legacy_prevout));
+        assert_eq!(&psbt[tx_end + 1..], &[0x00, 0x00]);
+
+        // P2SH may wrap segwit, so it keeps the record
+        let p2sh_prevout = TransactionOutput::new(100_000, Script::p2sh(&[0x33; 20]));
+        let psbt = tx.to_psbt(std::slice::from_ref(&p2sh_prevout));
+        assert_eq!(&psbt[tx_end + 1..tx_end + 3], &[0x01, 0x01]);
+    }
+
+    #[test]
+    fn test_signature_sighash_type() {
+        // Signature from the scriptSig of the block 170 transaction (SIGHASH_ALL)
+        let sig = hex::decode(concat!(
+            "304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd41",
+            "0220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901"
+        ))
+        .unwrap();
+        assert_eq!(signature_sighash_type(&sig), Some(0x01));
+
+        let mut single_acp = sig.clone();
+        *single_acp.last_mut().unwrap() = 0x83;
+        assert_eq!(signature_sighash_type(&single_acp), Some(0x83));
+
+        // Missing the sighash byte, or not DER at all
+        assert_eq!(signature_sighash_type(&sig[..sig.len() - 1]), None);
+        assert_eq!(signature_sighash_type(&[0x02, 0x01, 0x01]), None);
+        assert_eq!(signature_sighash_type(&[]), None);
+    }
+
+    #[test]
+    fn test_has_non_low_s_signature() {
+        let r = "4e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd41";
+        let low_s = "181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d09";
+        // n - low_s, which needs a leading zero byte in DER
+        let high_s = "00e7eadd137135f821b79f5b5322ed6f6137921779f39c5a19b7b03ce459a92438";
+
+        let script_sig = |s: &str| {
+            let s_bytes = hex::decode(s).unwrap();
+            let mut der = vec![0x30, (4 + 32 + s_bytes.len()) as u8, 0x02, 0x20];
+            der.extend_from_slice(&hex::decode(r).unwrap());
+            der.push(0x02);
+            der.push(s_bytes.len() as u8);
+            der.extend_from_slice(&s_bytes);
+            der.push(0x01);
+
+            let mut script = vec![der.len() as u8];
+            script.extend_from_slice(&der);
+            // Followed by a compressed pubkey push
+            script.push(0x21);
+            script.extend_from_slice(&[0x02; 33]);
+            Script::new(script)
+        };
+
+        assert!(!script_sig(low_s).has_non_low_s_signature());
+        assert!(script_sig(high_s).has_non_low_s_signature());
+        assert!(!Script::new(vec![]).has_non_low_s_signature());
+    }
+
+    #[test]
+    fn test_bitcoin_error_display_and_box() {
+        assert_eq!(
+            BitcoinError::InsufficientBytes {
+                offset: 4,
+                needed: 36
+            }
+            .to_string(),
+            "not enough bytes to decode: 36 needed at offset 4"
+        );
+        assert_eq!(
+            BitcoinError::InvalidFormat.to_string(),
+            "malformed encoding"
+        );
+        assert_eq!(
+            BitcoinError::TruncatedScript {
+                offset: 41,
+                needed: 73
+            }
+            .to_string(),
+            "script length exceeds the remaining bytes: 73 needed at offset 41"
+        );
+
+        fn decode(bytes: &[u8]) -> Result<u64, Box<dyn std::error::Error>> {
+            let (cs, _) = CompactSize::from_bytes(bytes)?;
+            Ok(cs.value)
+        }
+        assert_eq!(decode(&[0x2A]).unwrap(), 42);
+        let err = decode(&[0xFD]).unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "not enough bytes to decode: 3 needed at offset 0"
+        );
+    }
+
+    #[test]
+    fn test_huge_input_count_does_not_preallocate() {
+        let mut bytes = 1u32.to_le_bytes().to_vec();
+        bytes.push(0xFF);
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 16]);
+
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&bytes),
+            Err(BitcoinError::InsufficientBytes {
+                offset: 13,
+                needed: 36
+            })
+        );
+    }
+
+    #[test]
+    fn test_segwit_tx_roundtrip() {
+        let raw = hex::decode(BIP143_P2WPKH_TX).unwrap();
+
+        let (tx, consumed) = BitcoinTransaction::from_bytes(&raw).unwrap();
+        assert_eq!(consumed, raw.len());
+        assert_eq!(tx.inputs.len(), 2);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.lock_time, 0x11);
+        assert_eq!(tx.witnesses.len(), 2);
+        assert!(tx.witnesses[0].is_empty());
+        assert_eq!(tx.witnesses[1].len(), 2);
+        assert_eq!(tx.witnesses[1][1].len(), 33);
+        assert_eq!(tx.to_bytes(), raw);
+
+        // Dropping the witnesses falls back to the legacy encoding
+        let mut legacy = tx.clone();
+        legacy.witnesses.clear();
+        let legacy_bytes = legacy.to_bytes();
+        assert_eq!(&legacy_bytes[4..5], &[0x02]);
+        assert_eq!(
+            legacy_bytes.len(),
+            raw.len() - 2 - (1 + 1 + 1 + 0x47 + 1 + 33)
+        );
+        let (parsed, _) = BitcoinTransaction::from_bytes(&legacy_bytes).unwrap();
+        assert_eq!(parsed, legacy);
+    }
+
+    #[test]
+    fn test_txid_from_hex_display_roundtrip() {
+        let s = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16";
+        let txid = Txid::from_hex(s).unwrap();
+        assert_eq!(txid.0[0], 0x16);
+        assert_eq!(txid.0[31], 0xF4);
+        assert_eq!(txid.to_string(), s);
+        assert_eq!(
+            serde_json::from_str::<Txid>(&format!("\"{}\"", s)).unwrap(),
+            txid
+        );
+
+        assert_eq!(Txid::from_hex(&s[1..]), Err(BitcoinError::InvalidFormat));
+        assert_eq!(Txid::from_hex(&s[2..]), Err(BitcoinError::InvalidFormat));
+        assert_eq!(
+            Txid::from_hex(&s.replace('f', "g")),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_dummy_signature_sizes() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(5), 2),
+            Script::new(vec![0xAA]),
+            0xFFFFFFFD,
+        );
+
+        let p2pkh = input.with_dummy_signature(InputType::P2PKH);
+        assert_eq!(p2pkh.script_sig.len(), 1 + 72 + 1 + 33);
+        assert_eq!(p2pkh.previous_output, input.previous_output);
+        assert_eq!(p2pkh.sequence, input.sequence);
+        assert_eq!(p2pkh.to_bytes().len(), 36 + 1 + 107 + 4);
+        assert!(InputType::P2PKH.dummy_witness().is_empty());
+
+        let p2wpkh = input.with_dummy_signature(InputType::P2WPKH);
+        assert!(p2wpkh.script_sig.is_empty());
+        let witness = InputType::P2WPKH.dummy_witness();
+        assert_eq!(witness.len(), 2);
+        assert_eq!(witness[0].len(), 72);
+        assert_eq!(witness[1].len(), 33);
+    }
+
+    #[test]
+    fn test_has_uncompressed_pubkey() {
+        let mut uncompressed = vec![0x41, 0x04];
+        uncompressed.extend_from_slice(&[0x11; 64]);
+        uncompressed.push(0xAC); // OP_CHECKSIG
+        assert!(Script::new(uncompressed).has_uncompressed_pubkey());
+
+        let mut compressed = vec![0x21, 0x02];
+        compressed.extend_from_slice(&[0x11; 32]);
+        compressed.push(0xAC);
+        assert!(!Script::new(compressed).has_uncompressed_pubkey());
+    }
+
+    #[test]
+    fn test_txid_and_wtxid() {
+        let raw = hex::decode(BLOCK_170_TX).unwrap();
+        let (tx, _) = BitcoinTransaction::from_bytes(&raw).unwrap();
+        assert_eq!(
+            tx.txid().to_string(),
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16"
+        );
+        assert_eq!(tx.txid(), tx.wtxid());
+
+        // Adding a witness changes the wtxid but not the txid
+        let mut segwit = tx.clone();
+        segwit.witnesses = vec![vec![vec![0x01; 4]]];
+        assert_eq!(segwit.txid(), tx.txid());
+        assert_ne!(segwit.wtxid(), tx.wtxid());
+    }
+
+    #[test]
+    fn test_prevout_requests() {
+        let spend = |txid: [u8; 32], vout: u32| {
+            TransactionInput::new(OutPoint::new(txid, vout), Script::new(vec![]), 0xFFFFFFFF)
+        };
+        let tx = BitcoinTransaction::new(
+            2,
+            vec![
+                spend(dummy_txid(1), 0),
+                spend([0u8; 32], 0xFFFFFFFF),
+                spend(dummy_txid(2), 3),
+                spend(dummy_txid(1), 0),
+            ],
+            vec![],
+            0,
+        );
+        assert_eq!(
+            tx.prevout_requests(),
+            vec![
+                OutPoint::new(dummy_txid(1), 0),
+                OutPoint::new(dummy_txid(2), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_all() {
+        let first = BitcoinTransaction::new(
+            1,
+            vec![TransactionInput::new(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(vec![0x51]),
+                0xFFFFFFFF,
+            )],
+            vec![TransactionOutput::new(1_000, Script::new(vec![0x51]))],
+            0,
+        );
+        let mut second = BitcoinTransaction::new(
+            2,
+            vec![TransactionInput::new(
+                OutPoint::new(dummy_txid(2), 1),
+                Script::new(vec![]),
+                0xFFFFFFFD,
+            )],
+            vec![TransactionOutput::new(2_000, Script::p2wpkh(&[0x01; 20]))],
+            100,
+        );
+        second.witnesses = vec![vec![vec![0xAB; 72], vec![0x02; 33]]];
+
+        let mut buf = first.to_bytes();
+        buf.extend_from_slice(&second.to_bytes());
+        assert_eq!(
+            BitcoinTransaction::from_bytes_all(&buf).unwrap(),
+            vec![first, second]
+        );
+        assert!(BitcoinTransaction::from_bytes_all(&[]).unwrap().is_empty());
+
+        buf.extend_from_slice(&[0x01, 0x00]);
+        assert_eq!(
+            BitcoinTransaction::from_bytes_all(&buf),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_output_bytes_for_single() {
+        let outputs = vec![
+            TransactionOutput::new(1_000, Script::p2wpkh(&[0x01; 20])),
+            TransactionOutput::new(2_000, Script::p2pkh(&[0x02; 20])),
+        ];
+        let tx = BitcoinTransaction::new(2, vec![], outputs.clone(), 0);
+        assert_eq!(tx.output_bytes_for_single(1), Some(outputs[1].to_bytes()));
+        assert_eq!(tx.output_bytes_for_single(2), None);
+    }
+
+    #[test]
+    fn test_weight_and_vsize() {
+        let (legacy, size) =
+            BitcoinTransaction::from_bytes(&hex::decode(BLOCK_170_TX).unwrap()).unwrap();
+        assert_eq!(legacy.weight(), 4 * size);
+        assert_eq!(legacy.vsize(), size);
+
+        // 343 bytes total, 233 without marker, flag and witnesses
+        let (segwit, total) =
+            BitcoinTransaction::from_bytes(&hex::decode(BIP143_P2WPKH_TX).unwrap()).unwrap();
+        assert_eq!(total, 343);
+        assert_eq!(segwit.weight(), 233 * 3 + 343);
+        assert_eq!(segwit.vsize(), 261);
+        assert!(segwit.vsize() < total);
+    }
+
+    #[test]
+    fn test_tx_shape() {
+        let make = |inputs: usize, outputs: usize| {
+            let inputs = (0..inputs)
+                .map(|i| {
+                    TransactionInput::new(
+                        OutPoint::new(dummy_txid(i as u8), 0),
+                        Script::new(vec![]),
+                        0xFFFFFFFF,
+                    )
+                })
+                .collect();
+            let outputs = (0..outputs)
+                .map(|i| TransactionOutput::new(1_000 * (i as u64 + 1), Script::new(vec![0x51])))
+                .collect();
+            BitcoinTransaction::new(2, inputs, outputs, 0)
+        };
+
+        assert_eq!(make(5, 1).shape(), TxShape::Consolidation);
+        assert_eq!(make(1, 2).shape(), TxShape::Payment);
+        assert_eq!(make(2, 2).shape(), TxShape::Payment);
+        assert_eq!(make(1, 10).shape(), TxShape::Batch);
+        assert_eq!(make(4, 4).shape(), TxShape::Unknown);
+        assert_eq!(make(0, 0).shape(), TxShape::Unknown);
+    }
+
+    #[test]
+    fn test_script_disassemble() {
+        let hash = [0x62; 20];
+        let ops = Script::p2pkh(&hash).disassemble().unwrap();
+        assert_eq!(
+            ops,
+            vec![
+                ScriptOp::Op(0x76),
+                ScriptOp::Op(0xA9),
+                ScriptOp::PushBytes(hash.to_vec()),
+                ScriptOp::Op(0x88),
+                ScriptOp::Op(0xAC),
+            ]
+        );
+
+        let pushdata = Script::new(vec![0x00, 0x4C, 0x02, 0xAA, 0xBB, 0x4D, 0x01, 0x00, 0xCC]);
+        assert_eq!(
+            pushdata.disassemble().unwrap(),
+            vec![
+                ScriptOp::PushBytes(vec![]),
+                ScriptOp::PushData1(vec![0xAA, 0xBB]),
+                ScriptOp::PushData2(vec![0xCC]),
+            ]
+        );
+
+        // OP_PUSHDATA1 claiming 5 bytes with only 2 left, and a cut-off length field
+        assert_eq!(
+            Script::new(vec![0x4C, 0x05, 0x01, 0x02]).disassemble(),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(
+            Script::new(vec![0x4E, 0x01, 0x00]).disassemble(),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_output_cltv_locktime() {
+        // <800000> OP_CHECKLOCKTIMEVERIFY OP_DROP OP_DUP OP_HASH160 <hash> OP_EQUALVERIFY OP_CHECKSIG
+        let mut script = vec![0x03, 0x00, 0x35, 0x0C, 0xB1, 0x75];
+        script.extend_from_slice(&Script::p2pkh(&[0x09; 20]).bytes);
+        let output = TransactionOutput::new(10_000, Script::new(script));
+        assert_eq!(output.cltv_locktime(), Some(800_000));
+
+        // Small-int lock time
+        let small = TransactionOutput::new(1, Script::new(vec![0x5A, 0xB1, 0x75, 0x51]));
+        assert_eq!(small.cltv_locktime(), Some(10));
+
+        // Negative lock time and a plain P2PKH output
+        let negative = TransactionOutput::new(1, Script::new(vec![0x01, 0x81, 0xB1, 0x75]));
+        assert_eq!(negative.cltv_locktime(), None);
+        let plain = TransactionOutput::new(1, Script::p2pkh(&[0x09; 20]));
+        assert_eq!(plain.cltv_locktime(), None);
+    }
+
+    #[test]
+    fn test_script_type() {
+        let cases = [
+            (
+                "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac",
+                ScriptType::P2PKH,
+            ),
+            (
+                "a914b472a266d0bd89c13706a4132ccfb16f7c3b9fcb87",
+                ScriptType::P2SH,
+            ),
+            (
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                ScriptType::P2WPKH,
+            ),
+            (
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+                ScriptType::P2WSH,
+            ),
+            (
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                ScriptType::P2TR,
+            ),
+            ("6a0b68656c6c6f20776f726c64", ScriptType::OpReturn),
+            ("deadbeef", ScriptType::NonStandard),
+            // P2PKH pattern with a 19-byte hash
+            (
+                "76a91362e907b15cbf27d5425399ebf6f0fb50ebb88f88ac",
+                ScriptType::NonStandard,
+            ),
+        ];
+        for (spk, expected) in cases {
+            let script = Script::new(hex::decode(spk).unwrap());
+            assert_eq!(script.script_type(), expected, "{}", spk);
+        }
+    }
+
+    #[test]
+    fn test_transaction_builder() {
+        let outpoint = OutPoint::new(dummy_txid(0x10), 1);
+        let script_sig = Script::new(vec![0x01, 0x02]);
+        let script_pubkey = Script::p2wpkh(&[0x20; 20]);
+
+        let built = TransactionBuilder::new()
+            .add_input(outpoint.clone(), script_sig.clone(), 0xFFFFFFFD)
+            .add_output(25_000, script_pubkey.clone())
+            .build();
+
+        let expected = BitcoinTransaction::new(
+            2,
+            vec![TransactionInput::new(outpoint, script_sig, 0xFFFFFFFD)],
+            vec![TransactionOutput::new(25_000, script_pubkey)],
+            0,
+        );
+        assert_eq!(built.to_bytes(), expected.to_bytes());
+
+        let custom = TransactionBuilder::new().version(1).lock_time(500).build();
+        assert_eq!(custom.version, 1);
+        assert_eq!(custom.lock_time, 500);
+    }
+
+    #[test]
+    fn test_structural_fingerprint() {
+        let make = |txid: u8, pay: u64, change: u64| {
+            TransactionBuilder::new()
+                .add_input(
+                    OutPoint::new(dummy_txid(txid), 0),
+                    Script::new(vec![]),
+                    0xFFFFFFFD,
+                )
+                .add_output(pay, Script::p2pkh(&[txid; 20]))
+                .add_output(change, Script::p2wpkh(&[txid; 20]))
+                .build()
+        };
+        let a = make(1, 10_000, 5_000);
+        let b = make(2, 70_000, 1_234);
+        assert_eq!(a.structural_fingerprint(), b.structural_fingerprint());
+
+        let c = TransactionBuilder::new()
+            .add_input(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(vec![]),
+                0xFFFFFFFD,
+            )
+            .add_output(10_000, Script::p2wpkh(&[1; 20]))
+            .add_output(5_000, Script::p2wpkh(&[1; 20]))
+            .build();
+        assert_ne!(a.structural_fingerprint(), c.structural_fingerprint());
+    }
+
+    #[test]
+    fn test_locktime_is_effective() {
+        let with_sequence = |sequence: u32, lock_time: u32| {
+            TransactionBuilder::new()
+                .lock_time(lock_time)
+                .add_input(
+                    OutPoint::new(dummy_txid(1), 0),
+                    Script::new(vec![]),
+                    0xFFFFFFFF,
+                )
+                .add_input(
+                    OutPoint::new(dummy_txid(2), 0),
+                    Script::new(vec![]),
+                    sequence,
+                )
+                .build()
+        };
+        assert!(!with_sequence(0xFFFFFFFF, 800_000).locktime_is_effective());
+        assert!(with_sequence(0xFFFFFFFE, 800_000).locktime_is_effective());
+        assert!(!with_sequence(0xFFFFFFFE, 0).locktime_is_effective());
+    }
+
+    #[test]
+    fn test_from_bytes_exact() {
+        let raw = hex::decode(BIP143_P2WPKH_TX).unwrap();
+        let tx = BitcoinTransaction::from_bytes_exact(&raw).unwrap();
+        assert_eq!(tx.to_bytes(), raw);
+
+        let mut padded = raw.clone();
+        padded.push(0x00);
+        assert_eq!(
+            BitcoinTransaction::from_bytes_exact(&padded),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_extended_input_roundtrip() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(7), 1),
+            Script::new(vec![0x51]),
+            0xFFFFFFFD,
+        );
+
+        let bare = ExtendedTransactionInput::new(input.clone(), None, None);
+        let bytes = bare.to_bytes();
+        assert_eq!(bytes[0], 0x00);
+        assert_eq!(bytes.len(), 1 + input.to_bytes().len());
+        assert_eq!(
+            ExtendedTransactionInput::from_bytes(&bytes).unwrap(),
+            (bare, bytes.len())
+        );
+
+        let full =
+            ExtendedTransactionInput::new(input, Some(50_000), Some(Script::p2wpkh(&[0x11; 20])));
+        let bytes = full.to_bytes();
+        assert_eq!(bytes[0], 0x03);
+        assert_eq!(
+            ExtendedTransactionInput::from_bytes(&bytes).unwrap(),
+            (full, bytes.len())
+        );
+
+        // Flagged fields must be present
+        assert_eq!(
+            ExtendedTransactionInput::from_bytes(&bytes[..bytes.len() - 23]),
+            Err(BitcoinError::InsufficientBytes {
+                offset: bytes.len() - 23,
+                needed: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_compact_size_from_reader() {
+        for value in [0x42u64, 0x1234, 0x1234_5678, 0x1234_5678_9ABC_DEF0] {
+            let bytes = CompactSize::new(value).to_bytes();
+            let mut cursor = Cursor::new(bytes.clone());
+            assert_eq!(CompactSize::from_reader(&mut cursor).unwrap().value, value);
+            assert_eq!(cursor.position() as usize, bytes.len());
+        }
+
+        let mut truncated = Cursor::new(vec![0xFE, 0x01, 0x02]);
+        let err = CompactSize::from_reader(&mut truncated).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_to_writer_matches_to_bytes() {
+        let tx = BitcoinTransaction::from_bytes(&hex::decode(BIP143_P2WPKH_TX).unwrap())
+            .unwrap()
+            .0;
+
+        let mut buf = Vec::new();
+        tx.to_writer(&mut buf).unwrap();
+        assert_eq!(buf, tx.to_bytes());
+
+        for value in [0x42u64, 0x1234, 0x1234_5678, 0x1234_5678_9ABC_DEF0] {
+            let cs = CompactSize::new(value);
+            let mut buf = Vec::new();
+            cs.to_writer(&mut buf).unwrap();
+            assert_eq!(buf, cs.to_bytes());
+        }
+
+        let input = &tx.inputs[0];
+        let mut buf = Vec::new();
+        input.previous_output.to_writer(&mut buf).unwrap();
+        assert_eq!(buf, input.previous_output.to_bytes());
+
+        let mut buf = Vec::new();
+        input.script_sig.to_writer(&mut buf).unwrap();
+        assert_eq!(buf, input.script_sig.to_bytes());
+
+        let mut buf = Vec::new();
+        input.to_writer(&mut buf).unwrap();
+        assert_eq!(buf, input.to_bytes());
+
+        let mut buf = Vec::new();
+        tx.outputs[0].to_writer(&mut buf).unwrap();
+        assert_eq!(buf, tx.outputs[0].to_bytes());
+    }
+
+    #[test]
+    fn test_batching_savings() {
+        let payment = |vout: u32, outputs: &[[u8; 20]]| {
+            outputs
+                .iter()
+                .fold(
+                    TransactionBuilder::new().add_input(
+                        OutPoint::new(dummy_txid(1), vout),
+                        Script::new(vec![]),
+                        0xFFFFFFFF,
+                    ),
+                    |builder, hash| builder.add_output(10_000, Script::p2wpkh(hash)),
+                )
+                .build()
+        };
+
+        let individual = [payment(0, &[[0xAA; 20]]), payment(1, &[[0xBB; 20]])];
+        let batched = payment(0, &[[0xAA; 20], [0xBB; 20]]);
+
+        // 82 vB each separately, 113 vB batched
+        assert_eq!(individual[0].vsize(), 82);
+        assert_eq!(batched.vsize(), 113);
+        assert_eq!(batching_savings(&individual, &batched), 51);
+        assert_eq!(batching_savings(&individual[..1], &batched), -31);
+    }
+
+    #[test]
+    fn test_roundtrip_divergence() {
+        let raw = hex::decode(BLOCK_170_TX).unwrap();
+        assert_eq!(BitcoinTransaction::roundtrip_divergence(&raw), None);
+
+        // Input count 1 written non-canonically as FD 01 00 decodes but re-encodes as 01
+        let mut non_canonical = raw[..4].to_vec();
+        non_canonical.extend_from_slice(&[0xFD, 0x01, 0x00]);
+        non_canonical.extend_from_slice(&raw[5..]);
+        assert_eq!(
+            BitcoinTransaction::roundtrip_divergence(&non_canonical),
+            Some(4)
+        );
+
+        let mut trailing = raw.clone();
+        trailing.push(0x00);
+        assert_eq!(
+            BitcoinTransaction::roundtrip_divergence(&trailing),
+            Some(raw.len())
+        );
+
+        assert_eq!(
+            BitcoinTransaction::roundtrip_divergence(&raw[..10]),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_error_offsets_point_into_buffer() {
+        let raw = hex::decode(BLOCK_170_TX).unwrap();
+
+        // Version, input count and the 36-byte outpoint put the scriptSig prefix at 41
+        assert_eq!(raw[41], 0x48);
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&raw[..60]),
+            Err(BitcoinError::TruncatedScript {
+                offset: 41,
+                needed: 1 + 0x48
+            })
+        );
+
+        // Cut inside the first output's value: the output starts after the
+        // scriptSig, the 4-byte sequence and the 1-byte output count
+        let output_start = 41 + 1 + 0x48 + 4 + 1;
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&raw[..output_start + 3]),
+            Err(BitcoinError::InsufficientBytes {
+                offset: output_start,
+                needed: 8
+            })
+        );
+
+        // Positions reported through a cursor are absolute in its buffer
+        let mut buf = vec![0xEE; 5];
+        buf.extend_from_slice(&raw[..60]);
+        let mut cursor = Cursor::new(buf.as_slice());
+        cursor.set_position(5);
+        assert_eq!(
+            BitcoinTransaction::decode_from_cursor(&mut cursor),
+            Err(BitcoinError::TruncatedScript {
+                offset: 46,
+                needed: 73
+            })
+        );
+    }
+
+    #[test]
+    fn test_outpoint_hash_set() {
+        use std::collections::HashSet;
+
+        let mut seen = HashSet::new();
+        assert!(seen.insert(OutPoint::new(dummy_txid(1), 0)));
+        assert!(seen.insert(OutPoint::new(dummy_txid(1), 1)));
+        assert!(seen.insert(OutPoint::new(dummy_txid(2), 0)));
+        assert!(!seen.insert(OutPoint::new(dummy_txid(1), 0)));
+        assert_eq!(seen.len(), 3);
+
+        let sizes: HashSet<CompactSize> = [1, 1, 300].into_iter().map(CompactSize::new).collect();
+        assert_eq!(sizes.len(), 2);
+    }
+
+    #[test]
+    fn test_outpoint_sorting() {
+        let mut outpoints = vec![
+            OutPoint::new(dummy_txid(2), 0),
+            OutPoint::new(dummy_txid(1), 5),
+            OutPoint::new(dummy_txid(1), 2),
+        ];
+        outpoints.sort();
+        assert_eq!(
+            outpoints,
+            vec![
+                OutPoint::new(dummy_txid(1), 2),
+                OutPoint::new(dummy_txid(1), 5),
+                OutPoint::new(dummy_txid(2), 0),
+            ]
+        );
+
+        // Txids compare by their internal byte array, first byte most significant
+        let mut low_first = [0u8; 32];
+        low_first[31] = 0xFF;
+        let mut high_first = [0u8; 32];
+        high_first[0] = 0x01;
+        assert!(Txid(low_first) < Txid(high_first));
+    }
+
+    #[test]
+    fn test_nested_segwit_type() {
+        let nested = |redeem: Script| {
+            let mut script_sig = vec![redeem.len() as u8];
+            script_sig.extend_from_slice(&redeem);
+            TransactionInput::new(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(script_sig),
+                0xFFFFFFFF,
+            )
+        };
+
+        let p2sh_p2wpkh = nested(Script::p2wpkh(&[0x22; 20]));
+        assert_eq!(p2sh_p2wpkh.nested_segwit_type(), Some(ScriptType::P2WPKH));
+
+        let mut p2wsh = vec![0x00, 0x20];
+        p2wsh.extend_from_slice(&[0x33; 32]);
+        let p2sh_p2wsh = nested(Script::new(p2wsh));
+        assert_eq!(p2sh_p2wsh.nested_segwit_type(), Some(ScriptType::P2WSH));
+
+        // A pushed P2PKH script is not nested segwit, nor is a native spend
+        assert_eq!(
+            nested(Script::p2pkh(&[0x44; 20])).nested_segwit_type(),
+            None
+        );
+        let native = TransactionInput::new(
+            OutPoint::new(dummy_txid(1), 0),
+            Script::new(vec![]),
+            0xFFFFFFFF,
+        );
+        assert_eq!(native.nested_segwit_type(), None);
+    }
+
+    #[test]
+    fn test_diff_json() {
+        let original = BitcoinTransaction::from_bytes(&hex::decode(BLOCK_170_TX).unwrap())
+            .unwrap()
+            .0;
+        assert_eq!(original.diff_json(&original), serde_json::json!({}));
+
+        // RBF-style replacement: signal via sequence and lower the change output
+        let mut replacement = original.clone();
+        replacement.inputs[0].sequence = 0xFFFFFFFD;
+        replacement.outputs[1].value -= 1_000;
+        replacement.lock_time = 1;
+
+        let diff = original.diff_json(&replacement);
+        let keys: Vec<&String> = diff.as_object().unwrap().keys().collect();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(diff["lock_time"], serde_json::json!({ "old": 0, "new": 1 }));
+        assert_eq!(diff["inputs"]["0"]["new"]["sequence"], 0xFFFFFFFDu32);
+        assert_eq!(
+            diff["outputs"]["1"]["new"]["value"],
+            original.outputs[1].value - 1_000
+        );
+        assert!(diff["outputs"].get("0").is_none());
+
+        // Entries present on one side only pair with null
+        replacement.outputs.pop();
+        let diff = original.diff_json(&replacement);
+        assert!(diff["outputs"]["1"]["new"].is_null());
+    }
+
+    #[test]
+    fn test_sort_bip69() {
+        // Internal order puts `a` after `b`; BIP69 compares reversed bytes, so `a` is first
+        let mut a = [0u8; 32];
+        a[0] = 0x01;
+        let mut b = [0u8; 32];
+        b[31] = 0x01;
+
+        let mut tx = TransactionBuilder::new()
+            .add_input(OutPoint::new(b, 1), Script::new(vec![]), 0xFFFFFFFF)
+            .add_input(OutPoint::new(b, 0), Script::new(vec![]), 0xFFFFFFFF)
+            .add_input(OutPoint::new(a, 0), Script::new(vec![]), 0xFFFFFFFF)
+            .add_output(2_000, Script::p2wpkh(&[0x01; 20]))
+            .add_output(1_000, Script::p2wpkh(&[0x02; 20]))
+            .add_output(1_000, Script::p2wpkh(&[0x01; 20]))
+            .build();
+        tx.witnesses = vec![vec![vec![0xB1]], vec![vec![0xB0]], vec![vec![0xA0]]];
+
+        tx.sort_bip69();
+        let order: Vec<_> = tx
+            .inputs
+            .iter()
+            .map(|input| (input.previous_output.txid.0, input.previous_output.vout))
+            .collect();
+        assert_eq!(order, vec![(a, 0), (b, 0), (b, 1)]);
+        assert_eq!(
+            tx.witnesses,
+            vec![vec![vec![0xA0]], vec![vec![0xB0]], vec![vec![0xB1]]]
+        );
+        assert_eq!(
+            tx.outputs,
+            vec![
+                TransactionOutput::new(1_000, Script::p2wpkh(&[0x01; 20])),
+                TransactionOutput::new(1_000, Script::p2wpkh(&[0x02; 20])),
+                TransactionOutput::new(2_000, Script::p2wpkh(&[0x01; 20])),
+            ]
+        );
+
+        let sorted = tx.clone();
+        tx.sort_bip69();
+        assert_eq!(tx, sorted);
+    }
+
+    #[test]
+    fn test_fee_and_fee_rate() {
+        let tx = TransactionBuilder::new()
+            .add_input(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(vec![]),
+                0xFFFFFFFF,
+            )
+            .add_output(10_000, Script::p2wpkh(&[0xAA; 20]))
+            .build();
+        assert_eq!(tx.vsize(), 82);
+
+        assert_eq!(tx.fee(&[10_820]), Ok(820));
+        assert_eq!(tx.fee_rate(&[10_820]), Ok(10.0));
+        assert_eq!(tx.fee(&[10_000]), Ok(0));
+
+        // Over-spend, and amounts that don't match the inputs
+        assert_eq!(tx.fee(&[9_999]), Err(BitcoinError::InvalidFormat));
+        assert_eq!(tx.fee_rate(&[9_999]), Err(BitcoinError::InvalidFormat));
+        assert_eq!(tx.fee(&[]), Err(BitcoinError::InvalidFormat));
+        assert_eq!(tx.fee(&[10_820, 1]), Err(BitcoinError::InvalidFormat));
+    }
+
+    #[test]
+    fn test_amount_redacted_bytes() {
+        let tx = BitcoinTransaction::from_bytes(&hex::decode(BIP143_P2WPKH_TX).unwrap())
+            .unwrap()
+            .0;
+        let redacted = BitcoinTransaction::from_bytes(&tx.to_amount_redacted_bytes())
+            .unwrap()
+            .0;
+
+        assert!(redacted.outputs.iter().all(|output| output.value == 0));
+        for (original, output) in tx.outputs.iter().zip(&redacted.outputs) {
+            assert_eq!(output.script_pubkey, original.script_pubkey);
+        }
+        assert_eq!(redacted.version, tx.version);
+        assert_eq!(redacted.inputs, tx.inputs);
+        assert_eq!(redacted.witnesses, tx.witnesses);
+        assert_eq!(redacted.lock_time, tx.lock_time);
+        assert_eq!(tx.to_amount_redacted_bytes().len(), tx.to_bytes().len());
+    }
+
+    #[test]
+    fn test_value_flow() {
+        let tx = TransactionBuilder::new()
+            .add_input(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(vec![]),
+                0xFFFFFFFF,
+            )
+            .add_input(
+                OutPoint::new(dummy_txid(2), 3),
+                Script::new(vec![]),
+                0xFFFFFFFF,
+            )
+            .add_output(30_000, Script::p2wpkh(&[0xAA; 20]))
+            .add_output(15_000, Script::p2wpkh(&[0xBB; 20]))
+            .build();
+        let prevouts = [
+            TransactionOutput::new(20_000, Script::p2wpkh(&[0x01; 20])),
+            TransactionOutput::new(26_000, Script::p2wpkh(&[0x02; 20])),
+        ];
+
+        let flow = tx.value_flow(&prevouts).unwrap();
+        assert_eq!(
+            flow,
+            ValueFlow {
+                total_input: 46_000,
+                total_output: 45_000,
+                fee: 1_000,
+            }
+        );
+        assert_eq!(flow.total_output + flow.fee, flow.total_input);
+
+        let overflowing = [
+            TransactionOutput::new(u64::MAX, Script::new(vec![])),
+            TransactionOutput::new(1, Script::new(vec![])),
+        ];
+        assert_eq!(
+            tx.value_flow(&overflowing),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(
+            tx.value_flow(&prevouts[..1]),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_script_from_bytes_checked() {
+        // Claims 100,000 bytes; rejected from the prefix alone
+        let mut claimed = vec![0xFE];
+        claimed.extend_from_slice(&100_000u32.to_le_bytes());
+        assert_eq!(
+            Script::from_bytes_checked(&claimed),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        let mut at_limit = vec![0xFD];
+        at_limit.extend_from_slice(&(MAX_SCRIPT_SIZE as u16).to_le_bytes());
+        at_limit.resize(3 + MAX_SCRIPT_SIZE, 0x51);
+        let (script, consumed) = Script::from_bytes_checked(&at_limit).unwrap();
+        assert_eq!(script.len(), MAX_SCRIPT_SIZE);
+        assert_eq!(consumed, at_limit.len());
+
+        // OP_PUSHDATA2 of exactly 520 bytes is allowed, 521 is not
+        let push = |len: usize| {
+            let mut body = vec![0x4D];
+            body.extend_from_slice(&(len as u16).to_le_bytes());
+            body.resize(3 + len, 0xAB);
+            Script::new(body).to_bytes()
+        };
+        let (script, _) = Script::from_bytes_checked(&push(MAX_SCRIPT_ELEMENT_SIZE)).unwrap();
+        assert_eq!(script.len(), 3 + 520);
+        assert_eq!(
+            Script::from_bytes_checked(&push(MAX_SCRIPT_ELEMENT_SIZE + 1)),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert!(Script::from_bytes(&push(MAX_SCRIPT_ELEMENT_SIZE + 1)).is_ok());
+
+        let small = Script::new(vec![0x51]).to_bytes();
+        assert_eq!(
+            Script::from_bytes_checked(&small),
+            Script::from_bytes(&small)
+        );
+    }
+
+    #[test]
+    fn test_output_count_limits() {
+        // Version, no inputs, then an output count of u64::MAX in a 20-byte buffer
+        let mut bytes = 1u32.to_le_bytes().to_vec();
+        bytes.push(0x00);
+        bytes.push(0xFF);
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 6]);
+
+        // Bounded pre-allocation: fails on the missing bytes, not on allocation
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&bytes),
+            Err(BitcoinError::InsufficientBytes {
+                offset: 14,
+                needed: 8
+            })
+        );
+
+        let limits = DecodeLimits { max_outputs: 100 };
+        assert_eq!(
+            BitcoinTransaction::from_bytes_with_limits(&bytes, &limits),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        let raw = hex::decode(BLOCK_170_TX).unwrap();
+        let (tx, _) =
+            BitcoinTransaction::from_bytes_with_limits(&raw, &DecodeLimits { max_outputs: 2 })
+                .unwrap();
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(
+            BitcoinTransaction::from_bytes_with_limits(&raw, &DecodeLimits { max_outputs: 1 }),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_script_ref_borrows() {
+        let raw = hex::decode(BLOCK_170_TX).unwrap();
+        // scriptSig of the only input starts after version, count and outpoint
+        let source = &raw[41..];
+
+        let (borrowed, consumed) = ScriptRef::from_bytes(source).unwrap();
+        // The body points into `source` rather than a fresh allocation
+        assert_eq!(borrowed.bytes.as_ptr(), source[1..].as_ptr());
+        assert_eq!(borrowed.bytes.len(), 0x48);
+
+        let (owned, owned_consumed) = Script::from_bytes(source).unwrap();
+        assert_eq!(consumed, owned_consumed);
+        assert_eq!(borrowed.to_owned(), owned);
+
+        assert_eq!(
+            ScriptRef::from_bytes(&[0x05, 0xAA]),
+            Err(BitcoinError::TruncatedScript {
+                offset: 0,
+                needed: 6
+            })
+        );
+    }
+
+    #[test]
+    fn test_compact_size_serialized_len() {
+        for (value, len) in [
+            (0xFC, 1),
+            (0xFD, 3),
+            (0xFFFF, 3),
+            (0x10000, 5),
+            (0xFFFFFFFF, 5),
+            (0x100000000, 9),
+        ] {
+            let cs = CompactSize::new(value);
+            assert_eq!(cs.serialized_len(), len);
+            assert_eq!(cs.serialized_len(), cs.to_bytes().len());
+        }
+    }
+
+    #[test]
+    fn test_op_return_multi() {
+        let output = TransactionOutput::op_return_multi(&[b"ab", &[0xCD; 3]]).unwrap();
+        assert_eq!(output.value, 0);
+        assert_eq!(
+            output.script_pubkey.bytes,
+            vec![0x6A, 0x02, b'a', b'b', 0x03, 0xCD, 0xCD, 0xCD]
+        );
+        assert_eq!(output.script_pubkey.script_type(), ScriptType::OpReturn);
+
+        // Exactly at the limit: OP_RETURN, OP_PUSHDATA1 80, then 80 bytes
+        let at_limit = TransactionOutput::op_return_multi(&[&[0u8; 80]]).unwrap();
+        assert_eq!(at_limit.script_pubkey.len(), MAX_OP_RETURN_SIZE);
+
+        assert_eq!(
+            TransactionOutput::op_return_multi(&[&[0u8; 78], &[0u8; 2]]),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_likely_change_output() {
+        let mut p2wsh = vec![0x00, 0x20];
+        p2wsh.extend_from_slice(&[0x55; 32]);
+
+        let mut tx = TransactionBuilder::new()
+            .add_input(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(vec![]),
+                0xFFFFFFFD,
+            )
+            .add_output(100_000, Script::new(p2wsh))
+            .add_output(37_461, Script::p2wpkh(&[0x66; 20]))
+            .build();
+        tx.witnesses = vec![InputType::P2WPKH.dummy_witness()];
+
+        // Round payment, odd change
+        assert_eq!(tx.likely_change_output(), Some(1));
+
+        // Equally round values: the output matching the P2WPKH input type wins
+        tx.outputs[1].value = 200_000;
+        assert_eq!(tx.likely_change_output(), Some(1));
+
+        // No distinguishing feature left
+        tx.outputs[0].script_pubkey = Script::p2wpkh(&[0x77; 20]);
+        assert_eq!(tx.likely_change_output(), None);
+
+        tx.outputs.truncate(1);
+        assert_eq!(tx.likely_change_output(), None);
+
+        // Equally round outputs and no inputs to compare script types against
+        let no_inputs = BitcoinTransaction::new(
+            2,
+            vec![],
+            vec![
+                TransactionOutput::new(1_000, Script::p2wpkh(&[0x01; 20])),
+                TransactionOutput::new(2_000, Script::p2wpkh(&[0x02; 20])),
+            ],
+            0,
+        );
+        assert_eq!(no_inputs.likely_change_output(), None);
+    }
+
+    #[test]
+    fn test_serialized_len_matches_to_bytes() {
+        let legacy = BitcoinTransaction::from_bytes(&hex::decode(BLOCK_170_TX).unwrap())
+            .unwrap()
+            .0;
+        let segwit = BitcoinTransaction::from_bytes(&hex::decode(BIP143_P2WPKH_TX).unwrap())
+            .unwrap()
+            .0;
+        let empty = BitcoinTransaction::new(2, vec![], vec![], 0);
+        let multi_input = TransactionBuilder::new()
+            .add_input(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(vec![0x51; 300]),
+                0,
+            )
+            .add_input(OutPoint::new(dummy_txid(2), 1), Script::new(vec![]), 0)
+            .add_input(OutPoint::new(dummy_txid(3), 2), Script::new(vec![]), 0)
+            .add_output(1_000, Script::p2wpkh(&[0x01; 20]))
+            .build();
+
+        for tx in [&legacy, &segwit, &empty, &multi_input] {
+            assert_eq!(tx.serialized_len(), tx.to_bytes().len());
+        }
+        assert_eq!(segwit.serialized_len(), 343);
+        assert_eq!(segwit.weight(), 1042);
+    }
+
+    #[test]
+    fn test_simple_send() {
+        let utxos = [
+            (OutPoint::new(dummy_txid(1), 0), 60_000),
+            (OutPoint::new(dummy_txid(2), 1), 50_000),
+        ];
+        let recipient = Script::p2pkh(&[0xAA; 20]);
+        let change = Script::p2wpkh(&[0xBB; 20]);
+
+        let tx = BitcoinTransaction::simple_send(
+            &utxos,
+            recipient.clone(),
+            100_000,
+            change.clone(),
+            2_000,
+        )
+        .unwrap();
+        assert_eq!(tx.inputs.len(), 2);
+        assert!(
+            tx.inputs
+                .iter()
+                .all(|input| input.sequence == 0xFFFFFFFD && input.script_sig.is_empty())
+        );
+        assert_eq!(tx.inputs[1].previous_output, utxos[1].0);
+        assert_eq!(
+            tx.outputs,
+            vec![
+                TransactionOutput::new(100_000, recipient.clone()),
+                TransactionOutput::new(8_000, change.clone()),
+            ]
+        );
+        assert_eq!(tx.fee(&[60_000, 50_000]), Ok(2_000));
+
+        // 293 sat of P2WPKH change is below its 294 sat dust threshold and goes to fees
+        let tx = BitcoinTransaction::simple_send(
+            &utxos,
+            recipient.clone(),
+            107_707,
+            change.clone(),
+            2_000,
+        )
+        .unwrap();
+        assert_eq!(tx.outputs.len(), 1);
+        let tx = BitcoinTransaction::simple_send(
+            &utxos,
+            recipient.clone(),
+            107_706,
+            change.clone(),
+            2_000,
+        )
+        .unwrap();
+        assert_eq!(tx.outputs[1].value, 294);
+
+        assert_eq!(
+            BitcoinTransaction::simple_send(&utxos, recipient, 108_001, change, 2_000),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_is_coinbase() {
+        let coinbase_input = TransactionInput::new(
+            OutPoint::new([0u8; 32], 0xFFFFFFFF),
+            Script::new(vec![0x03, 0x01, 0x02, 0x03]),
+            0xFFFFFFFF,
+        );
+        let payout = TransactionOutput::new(50_0000_0000, Script::p2wpkh(&[0x01; 20]));
+
+        let coinbase =
+            BitcoinTransaction::new(1, vec![coinbase_input.clone()], vec![payout.clone()], 0);
+        assert!(coinbase.is_coinbase());
+
+        let normal = BitcoinTransaction::from_bytes(&hex::decode(BLOCK_170_TX).unwrap())
+            .unwrap()
+            .0;
+        assert!(!normal.is_coinbase());
+
+        let two_nulls = BitcoinTransaction::new(
+            1,
+            vec![coinbase_input.clone(), coinbase_input],
+            vec![payout.clone()],
+            0,
+        );
+        assert!(!two_nulls.is_coinbase());
+        assert!(!BitcoinTransaction::new(1, vec![], vec![payout], 0).is_coinbase());
+    }
+
+    #[test]
+    fn test_try_from_slices() {
+        let raw = hex::decode(BLOCK_170_TX).unwrap();
+        let tx = BitcoinTransaction::try_from(&raw[..]).unwrap();
+        assert_eq!(tx.to_bytes(), raw);
+        let mut padded = raw.clone();
+        padded.push(0x00);
+        assert_eq!(
+            BitcoinTransaction::try_from(&padded[..]),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        let input_bytes = tx.inputs[0].to_bytes();
+        assert_eq!(
+            TransactionInput::try_from(&input_bytes[..]),
+            Ok(tx.inputs[0].clone())
+        );
+        assert!(TransactionInput::try_from(&input_bytes[..40]).is_err());
+
+        let outpoint_bytes = tx.inputs[0].previous_output.to_bytes();
+        assert_eq!(
+            OutPoint::try_from(&outpoint_bytes[..]),
+            Ok(tx.inputs[0].previous_output.clone())
+        );
+        assert_eq!(
+            OutPoint::try_from(&input_bytes[..]),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        assert_eq!(
+            Script::try_from(&[0x02, 0x51, 0x52][..]),
+            Ok(Script::new(vec![0x51, 0x52]))
+        );
+        assert_eq!(
+            Script::try_from(&[0x01, 0x51, 0x52][..]),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        assert_eq!(
+            CompactSize::try_from(&[0xFD, 0x00, 0x01][..]),
+            Ok(CompactSize::new(256))
+        );
+        assert_eq!(
+            CompactSize::try_from(&[0xFD, 0x00][..]),
+            Err(BitcoinError::InsufficientBytes {
+                offset: 0,
+                needed: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_input_address_reuse() {
+        let tx = TransactionBuilder::new()
+            .add_input(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(vec![]),
+                0xFFFFFFFF,
+            )
+            .add_input(
+                OutPoint::new(dummy_txid(2), 0),
+                Script::new(vec![]),
+                0xFFFFFFFF,
+            )
+            .add_input(
+                OutPoint::new(dummy_txid(3), 0),
+                Script::new(vec![]),
+                0xFFFFFFFF,
+            )
+            .add_output(1_000, Script::p2wpkh(&[0x01; 20]))
+            .build();
+
+        let shared = Script::p2wpkh(&[0xAA; 20]);
+        let prevouts = [
+            TransactionOutput::new(5_000, shared.clone()),
+            TransactionOutput::new(7_000, Script::p2wpkh(&[0xBB; 20])),
+            TransactionOutput::new(9_000, shared),
+        ];
+        assert_eq!(tx.input_address_reuse(&prevouts), 1);
+
+        let distinct = [
+            TransactionOutput::new(5_000, Script::p2wpkh(&[0x0A; 20])),
+            TransactionOutput::new(7_000, Script::p2wpkh(&[0x0B; 20])),
+            TransactionOutput::new(9_000, Script::p2wpkh(&[0x0C; 20])),
+        ];
+        assert_eq!(tx.input_address_reuse(&distinct), 0);
+    }
+
+    #[test]
+    fn test_outputs_hash_bip143() {
+        let tx = BitcoinTransaction::from_bytes(&hex::decode(BIP143_P2WPKH_TX).unwrap())
+            .unwrap()
+            .0;
+        // hashOutputs from the BIP143 native P2WPKH example
+        assert_eq!(
+            hex::encode(tx.outputs_hash()),
+            "863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5"
+        );
+
+        let mut changed = tx.clone();
+        changed.outputs[0].value += 1;
+        assert_ne!(changed.outputs_hash(), tx.outputs_hash());
+    }
+
+    #[test]
+    fn test_truncated_segwit_never_decodes() {
+        let raw = hex::decode(BIP143_P2WPKH_TX).unwrap();
+        for len in 0..raw.len() {
+            assert!(
+                BitcoinTransaction::from_bytes(&raw[..len]).is_err(),
+                "prefix of {} bytes decoded",
+                len
+            );
+        }
+
+        // Cut inside the first input's scriptSig: the SegWit error surfaces unchanged
+        assert_eq!(raw[43], 0x49);
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&raw[..60]),
+            Err(BitcoinError::TruncatedScript {
+                offset: 43,
+                needed: 1 + 0x49
+            })
+        );
+    }
+
+    #[test]
+    fn test_extra_witness_stacks_do_not_force_segwit() {
+        let mut tx = TransactionBuilder::new()
+            .add_input(
+                OutPoint::new(dummy_txid(1), 0),
+                Script::new(vec![]),
+                0xFFFFFFFF,
+            )
+            .add_output(1_000, Script::p2wpkh(&[0x01; 20]))
+            .build();
+        let legacy = tx.to_bytes();
+
+        // A non-empty stack past the last input is not serialized
+        tx.witnesses = vec![vec![], vec![vec![0x01]]];
+        assert_eq!(tx.to_bytes(), legacy);
+        assert_eq!(tx.serialized_len(), legacy.len());
+        assert_eq!(tx.weight(), legacy.len() * 4);
+
+        let (parsed, _) = BitcoinTransaction::from_bytes(&tx.to_bytes()).unwrap();
+        assert_eq!(parsed.inputs, tx.inputs);
+        assert_eq!(parsed.outputs, tx.outputs);
+    }
+
+    #[test]
+    fn test_empty_input_tx_roundtrip_not_segwit() {
+        // version | 0x00 input count | 0x01 output count -> "00 01" looks like marker + flag
+        let output = TransactionOutput::new(5_000, Script::new(vec![0x51]));
+        let tx = BitcoinTransaction::new(1, vec![], vec![output], 0);
+        let bytes = tx.to_bytes();
+        assert_eq!(&bytes[4..6], &[0x00, 0x01]);
+
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&bytes),
+            Ok((tx.clone(), bytes.len()))
+        );
+        assert_eq!(BitcoinTransaction::from_bytes_exact(&bytes), Ok(tx.clone()));
+        assert_eq!(BitcoinTransaction::try_from(&bytes[..]), Ok(tx.clone()));
+        assert_eq!(BitcoinTransaction::from_consensus_cached(&bytes), Ok(tx));
+    }
+
+    #[test]
+    fn test_to_psbt_bip174_creator_vector() {
+        // The Creator output from BIP 174's worked example: the unsigned tx and
+        // empty maps for its two inputs and two outputs
+        let expected = "70736274ff01009a020000000258e87a21b56daf0c23be8e7070456c336f7cbaa5c8757924f545887bb2abdd750000000000ffffffff838d0427d0ec650a68aa46bb0b098aea4422c071b2ca78352a077959d07cea1d0100000000ffffffff0270aaf00800000000160014d85c2b71d0060b09c9886aeb815e50991dda124d00e1f5050000000016001400aea9a2e5f0f876a588df5546e8742d1d87008f000000000000000000";
+        let raw = hex::decode(expected).unwrap();
+        let tx = BitcoinTransaction::from_bytes_exact(&raw[8..8 + 154]).unwrap();
+
+        assert_eq!(hex::encode(tx.to_psbt(&[])), expected);
+    }
+
+    #[test]
+    fn test_with_dummy_scripts_serialized_len() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(vec![0x01, 0x02, 0x03]),
+            0xFFFFFFFF,
+        );
+        let output = TransactionOutput::new(50_000, Script::p2wpkh(&[0x22; 20]));
+        let tx = BitcoinTransaction::new(2, vec![input.clone(), input], vec![output], 0);
+
+        let sized = tx.with_dummy_scripts(107, 25);
+        assert!(
+            sized
+                .inputs
+                .iter()
+                .all(|i| i.script_sig.bytes == vec![0u8; 107])
+        );
+        assert_eq!(sized.outputs[0].script_pubkey.bytes, vec![0u8; 25]);
+        // version + counts + 2 * (outpoint + 1 + 107 + sequence) + (value + 1 + 25) + lock_time
+        assert_eq!(
+            sized.serialized_len(),
+            4 + 1 + 2 * (36 + 1 + 107 + 4) + 1 + (8 + 1 + 25) + 4
+        );
+        assert_eq!(sized.serialized_len(), sized.to_bytes().len());
+
+        // A 253-byte script needs a 3-byte length prefix
+        assert_eq!(
+            tx.with_dummy_scripts(253, 0).serialized_len(),
+            4 + 1 + 2 * (36 + 3 + 253 + 4) + 1 + 9 + 4
+        );
+    }
+
+    #[test]
+    fn test_has_witness() {
+        let (tx, _) =
+            BitcoinTransaction::from_bytes(&hex::decode(BIP143_P2WPKH_TX).unwrap()).unwrap();
+        assert!(tx.has_witness());
+        assert_ne!(tx.txid(), tx.wtxid());
+
+        let mut legacy = tx.clone();
+        legacy.witnesses.clear();
+        assert!(!legacy.has_witness());
+        assert_eq!(legacy.txid(), legacy.wtxid());
+
+        // Empty stacks alone are not witness data
+        legacy.witnesses = vec![Vec::new(); legacy.inputs.len()];
+        assert!(!legacy.has_witness());
+    }
+
+    #[test]
+    fn test_sigops_cost() {
+        // A pushed 0xAC is data, not an OP_CHECKSIG
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(vec![0x01, 0xAC]),
+            0xFFFFFFFF,
+        );
+        let mut multisig = vec![0x51, 0x21];
+        multisig.extend_from_slice(&[0x02; 33]);
+        multisig.push(0x21);
+        multisig.extend_from_slice(&[0x03; 33]);
+        multisig.extend_from_slice(&[0x52, 0xAE]);
+        let outputs = vec![
+            TransactionOutput::new(10_000, Script::p2pkh(&[0x22; 20])),
+            TransactionOutput::new(10_000, Script::new(multisig)),
+            TransactionOutput::new(10_000, Script::p2wpkh(&[0x33; 20])),
+        ];
+        let tx = BitcoinTransaction::new(2, vec![input], outputs, 0);
+
+        assert_eq!(tx.outputs[0].script_pubkey.sigop_count(), 1);
+        assert_eq!(tx.outputs[1].script_pubkey.sigop_count(), 20);
+        assert_eq!(tx.sigops_cost(), (1 + 20) * 4);
+
+        // A truncated push makes the script count nothing
+        assert_eq!(Script::new(vec![0xAC, 0x02, 0x01]).sigop_count(), 0);
+    }
+
+    #[test]
+    fn test_canonical_bytes_ignores_witnesses() {
+        let (tx, _) =
+            BitcoinTransaction::from_bytes(&hex::decode(BIP143_P2WPKH_TX).unwrap()).unwrap();
+        let mut resigned = tx.clone();
+        resigned.witnesses[1][0] = vec![0x30; 71];
+
+        assert_ne!(tx.to_bytes(), resigned.to_bytes());
+        assert_eq!(tx.canonical_bytes(), resigned.canonical_bytes());
+        assert_eq!(&tx.canonical_bytes()[4..5], &[0x02]);
+
+        let mut stripped = tx.clone();
+        stripped.witnesses.clear();
+        assert_eq!(tx.canonical_bytes(), stripped.to_bytes());
+    }
+
+    #[test]
+    fn test_txid_of_raw_matches_parsed_txid() {
+        let legacy = hex::decode(BLOCK_170_TX).unwrap();
+        let segwit = hex::decode(BIP143_P2WPKH_TX).unwrap();
+        let mut stream = segwit.clone();
+        stream.extend_from_slice(&legacy);
+
+        let (first, first_len) = txid_of_raw(&stream).unwrap();
+        let (tx, consumed) = BitcoinTransaction::from_bytes(&stream).unwrap();
+        assert_eq!((first, first_len), (tx.txid(), consumed));
+        assert_eq!(first_len, segwit.len());
+
+        let (second, second_len) = txid_of_raw(&stream[first_len..]).unwrap();
+        let (tx, _) = BitcoinTransaction::from_bytes(&legacy).unwrap();
+        assert_eq!((second, second_len), (tx.txid(), legacy.len()));
+
+        assert!(txid_of_raw(&segwit[..segwit.len() - 1]).is_err());
+        assert!(txid_of_raw(&legacy[..3]).is_err());
+    }
+
+    #[test]
+    fn test_taproot_key_spend_signature() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(Vec::new()),
+            0xFFFFFFFD,
+        );
+        let output = TransactionOutput::new(50_000, Script::p2wpkh(&[0x22; 20]));
+        let mut tx = BitcoinTransaction::new(2, vec![input], vec![output], 0);
+        let sig = vec![0x5a; 64];
+
+        tx.witnesses = vec![vec![sig.clone()]];
+        assert_eq!(tx.taproot_key_spend_signature(0), Some(&sig[..]));
+
+        // The annex is skipped
+        tx.witnesses = vec![vec![sig.clone(), vec![0x50, 0x01]]];
+        assert_eq!(tx.taproot_key_spend_signature(0), Some(&sig[..]));
+
+        // Sighash-suffixed signatures are 65 bytes
+        let sig_with_type = vec![0x5a; 65];
+        tx.witnesses = vec![vec![sig_with_type.clone()]];
+        assert_eq!(tx.taproot_key_spend_signature(0), Some(&sig_with_type[..]));
+
+        // Script-path spends and other shapes have no key-path signature
+        tx.witnesses = vec![vec![sig.clone(), vec![0x51], vec![0xc0; 33]]];
+        assert_eq!(tx.taproot_key_spend_signature(0), None);
+        tx.witnesses = vec![vec![vec![0x30; 71]]];
+        assert_eq!(tx.taproot_key_spend_signature(0), None);
+        assert_eq!(tx.taproot_key_spend_signature(1), None);
+    }
+
+    #[test]
+    fn test_all_pushed_data() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(vec![0x02, 0xAA, 0xBB, 0x4C, 0x01, 0xCC]),
+            0xFFFFFFFF,
+        );
+        let outputs = vec![
+            TransactionOutput::new(10_000, Script::p2pkh(&[0x22; 20])),
+            TransactionOutput::new(0, Script::new(vec![0x6A, 0x03, b'a', b'b', b'c'])),
+        ];
+        let mut tx = BitcoinTransaction::new(2, vec![input], outputs, 0);
+        tx.witnesses = vec![vec![vec![0xDD; 2], Vec::new()]];
+
+        assert_eq!(
+            tx.all_pushed_data(),
+            vec![
+                vec![0xAA, 0xBB],
+                vec![0xCC],
+                vec![0x22; 20],
+                b"abc".to_vec(),
+                vec![0xDD; 2],
+                Vec::new(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_size_category() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(Vec::new()),
+            0xFFFFFFFF,
+        );
+        let output = TransactionOutput::new(50_000, Script::p2wpkh(&[0x22; 20]));
+        let tx = BitcoinTransaction::new(2, vec![input], vec![output], 0);
+
+        // 10 + 41 + 8 + 1 + script bytes
+        assert_eq!(tx.with_dummy_scripts(0, 190).vsize(), 250);
+        assert_eq!(
+            tx.with_dummy_scripts(0, 190).size_category(),
+            SizeCategory::Tiny
+        );
+        assert_eq!(
+            tx.with_dummy_scripts(0, 191).size_category(),
+            SizeCategory::Small
+        );
+        assert_eq!(
+            tx.with_dummy_scripts(0, 5_000).size_category(),
+            SizeCategory::Medium
+        );
+        assert_eq!(
+            tx.with_dummy_scripts(0, 50_000).size_category(),
+            SizeCategory::Large
+        );
+        assert_eq!(
+            tx.with_dummy_scripts(0, 100_000).size_category(),
+            SizeCategory::Huge
+        );
+    }
+
+    // Decodes each hex transaction and checks that it re-encodes to the same bytes
+    fn assert_roundtrip_all(hexes: &[&str]) {
+        for hex_tx in hexes {
+            let raw = hex::decode(hex_tx).unwrap();
+            let tx = BitcoinTransaction::from_bytes_exact(&raw)
+                .unwrap_or_else(|e| panic!("{} does not decode: {}", hex_tx, e));
+            assert_eq!(hex::encode(tx.to_bytes()), *hex_tx);
+            assert_eq!(tx.serialized_len(), raw.len());
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_dataset() {
+        // Genesis block coinbase, 4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b
+        const GENESIS_COINBASE_TX: &str = concat!(
+            "01000000010000000000000000000000000000000000000000000000000000000000000000",
+            "ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368",
+            "616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f75742066",
+            "6f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a671",
+            "30b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c38",
+            "4df7ba0b8d578a4c702b6bf11d5fac00000000"
+        );
+        let dataset = [GENESIS_COINBASE_TX, BLOCK_170_TX, BIP143_P2WPKH_TX];
+        assert_roundtrip_all(&dataset);
+
+        // The mainnet entries hash to their known txids
+        for (hex_tx, txid) in [
+            (
+                GENESIS_COINBASE_TX,
+                "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            ),
+            (
+                BLOCK_170_TX,
+                "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16",
+            ),
+        ] {
+            let tx = BitcoinTransaction::from_bytes_exact(&hex::decode(hex_tx).unwrap()).unwrap();
+            assert_eq!(tx.txid().to_string(), txid);
+        }
+    }
+
+    #[test]
+    fn test_describe_spend() {
+        let outpoint = OutPoint::new(dummy_txid(0x11), 0);
+        let p2wpkh = TransactionOutput::new(50_000, Script::p2wpkh(&[0x22; 20]));
+        let p2pkh = TransactionOutput::new(50_000, Script::p2pkh(&[0x22; 20]));
+
+        let native = TransactionInput::new(outpoint.clone(), Script::new(Vec::new()), 0xFFFFFFFF);
+        assert_eq!(native.describe_spend(Some(&p2wpkh)), "P2WPKH");
+
+        let mut script_sig = vec![0x47];
+        script_sig.extend_from_slice(&[0x30; 0x47]);
+        script_sig.push(0x21);
+        script_sig.extend_from_slice(&[0x02; 0x21]);
+        let legacy = TransactionInput::new(outpoint.clone(), Script::new(script_sig), 0xFFFFFFFF);
+        assert_eq!(legacy.describe_spend(Some(&p2pkh)), "P2PKH");
+        assert_eq!(legacy.describe_spend(None), "unknown");
+
+        // The scriptSig pushes a P2WPKH redeem script
+        let mut redeem = vec![0x16];
+        redeem.extend_from_slice(&Script::p2wpkh(&[0x33; 20]).bytes);
+        let nested = TransactionInput::new(outpoint.clone(), Script::new(redeem), 0xFFFFFFFF);
+        let p2sh = TransactionOutput::new(50_000, Script::p2sh(&[0x44; 20]));
+        assert_eq!(nested.describe_spend(Some(&p2sh)), "P2SH-P2WPKH");
+
+        let mut multisig = vec![0x52];
+        for key in [0x02, 0x03, 0x02] {
+            multisig.push(0x21);
+            multisig.extend_from_slice(&[key; 33]);
+        }
+        multisig.extend_from_slice(&[0x53, 0xAE]);
+        let bare = TransactionOutput::new(50_000, Script::new(multisig));
+        assert_eq!(native.describe_spend(Some(&bare)), "2-of-3 multisig");
+    }
+
+    #[test]
+    fn test_self_transfer_outputs() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(Vec::new()),
+            0xFFFFFFFF,
+        );
+        let owned = [Script::p2wpkh(&[0x01; 20]), Script::p2pkh(&[0x02; 20])];
+        let outputs = vec![
+            TransactionOutput::new(10_000, Script::p2wpkh(&[0x99; 20])),
+            TransactionOutput::new(20_000, owned[1].clone()),
+            TransactionOutput::new(30_000, Script::p2sh(&[0x01; 20])),
+            TransactionOutput::new(40_000, owned[0].clone()),
+        ];
+        let tx = BitcoinTransaction::new(2, vec![input], outputs, 0);
+
+        assert_eq!(tx.self_transfer_outputs(&owned), vec![1, 3]);
+        assert!(tx.self_transfer_outputs(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_to_bytes_validated() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(Vec::new()),
+            0xFFFFFFFF,
+        );
+        let output = TransactionOutput::new(90_000, Script::p2wpkh(&[0x22; 20]));
+        let tx = BitcoinTransaction::new(2, vec![input], vec![output], 0);
+
+        let funded = [TransactionOutput::new(100_000, Script::p2wpkh(&[0x33; 20]))];
+        assert_eq!(tx.to_bytes_validated(&funded), Ok(tx.to_bytes()));
+
+        let short = [TransactionOutput::new(89_999, Script::p2wpkh(&[0x33; 20]))];
+        assert_eq!(
+            tx.to_bytes_validated(&short),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(tx.to_bytes_validated(&[]), Err(BitcoinError::InvalidFormat));
+    }
+
+    #[test]
+    fn test_has_annex() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(Vec::new()),
+            0xFFFFFFFF,
+        );
+        let output = TransactionOutput::new(50_000, Script::p2wpkh(&[0x22; 20]));
+        let mut tx = BitcoinTransaction::new(2, vec![input], vec![output], 0);
+
+        tx.witnesses = vec![vec![vec![0x50, 0x01]]];
+        assert!(!tx.has_annex(0));
+
+        tx.witnesses = vec![vec![vec![0x5a; 64], vec![0x50, 0x01]]];
+        assert!(tx.has_annex(0));
+
+        tx.witnesses = vec![vec![vec![0x50; 64], vec![0x51, 0x50]]];
+        assert!(!tx.has_annex(0));
+        assert!(!tx.has_annex(1));
+    }
+
+    #[test]
+    fn test_witness_bytes() {
+        let raw = hex::decode(BIP143_P2WPKH_TX).unwrap();
+        let tx = BitcoinTransaction::from_bytes_exact(&raw).unwrap();
+
+        // Input 0 has an empty stack, input 1 a signature and a pubkey
+        let witness = tx.witness_bytes();
+        assert_eq!(witness[..3], [0x00, 0x02, 0x47]);
+        assert_eq!(witness.len(), 1 + 1 + 1 + 0x47 + 1 + 33);
+
+        // It sits right before the lock_time in the SegWit serialization
+        let witness_end = raw.len() - 4;
+        assert_eq!(&raw[witness_end - witness.len()..witness_end], &witness[..]);
+    }
+
+    #[test]
+    fn test_input_witness_summary() {
+        let mut tx = BitcoinTransaction::from_bytes(&hex::decode(BIP143_P2WPKH_TX).unwrap())
+            .unwrap()
+            .0;
+        assert_eq!(tx.input_witness_summary(), (1, 1));
+
+        tx.witnesses.clear();
+        assert_eq!(tx.input_witness_summary(), (0, 2));
+    }
+
+    #[test]
+    fn test_first_op_return() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(Vec::new()),
+            0xFFFFFFFF,
+        );
+        let outputs = vec![
+            TransactionOutput::new(10_000, Script::p2wpkh(&[0x22; 20])),
+            TransactionOutput::op_return_multi(&[b"omni", b"data"]).unwrap(),
+            TransactionOutput::new(0, Script::new(vec![0x6A, 0x02, 0xAB, 0xCD])),
+        ];
+        let mut tx = BitcoinTransaction::new(2, vec![input], outputs, 0);
+
+        assert_eq!(tx.first_op_return(), Some((1, b"omnidata".to_vec())));
+
+        // A non-push opcode after OP_RETURN leaves the raw bytes
+        tx.outputs[1] = TransactionOutput::new(0, Script::new(vec![0x6A, 0x51, 0x01]));
+        assert_eq!(tx.first_op_return(), Some((1, vec![0x51, 0x01])));
+
+        tx.outputs.truncate(1);
+        assert_eq!(tx.first_op_return(), None);
+    }
+
+    #[test]
+    fn test_bump_fee() {
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(Vec::new()),
+            0xFFFFFFFD,
+        );
+        let outputs = vec![
+            TransactionOutput::new(50_000, Script::p2wpkh(&[0x22; 20])),
+            TransactionOutput::new(10_000, Script::p2wpkh(&[0x33; 20])),
+        ];
+        let tx = BitcoinTransaction::new(2, vec![input], outputs, 0);
+
+        let bumped = tx.bump_fee(1_500, 1).unwrap();
+        assert_eq!(bumped.outputs[0], tx.outputs[0]);
+        assert_eq!(bumped.outputs[1].value, 8_500);
+        assert_eq!(bumped.inputs, tx.inputs);
+
+        // P2WPKH dust is 294 sats
+        assert!(tx.bump_fee(10_000 - 294, 1).is_ok());
+        assert_eq!(
+            tx.bump_fee(10_000 - 293, 1),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(tx.bump_fee(20_000, 1), Err(BitcoinError::InvalidFormat));
+        assert_eq!(tx.bump_fee(1, 2), Err(BitcoinError::InvalidFormat));
+    }
+
+    #[test]
+    fn test_p2wsh_witness_script() {
+        use sha2::{Digest, Sha256};
+
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(0x11), 0),
+            Script::new(Vec::new()),
+            0xFFFFFFFF,
+        );
+        let output = TransactionOutput::new(50_000, Script::p2wpkh(&[0x22; 20]));
+        let mut tx = BitcoinTransaction::new(2, vec![input], vec![output], 0);
+
+        // 2-of-2 multisig witness script
+        let mut witness_script = vec![0x52, 0x21];
+        witness_script.extend_from_slice(&[0x02; 33]);
+        witness_script.push(0x21);
+        witness_script.extend_from_slice(&[0x03; 33]);
+        witness_script.extend_from_slice(&[0x52, 0xAE]);
+        tx.witnesses = vec![vec![
+            Vec::new(),
+            vec![0x30; 71],
+            vec![0x30; 72],
+            witness_script.clone(),
+        ]];
+
+        let mut prevout = vec![0x00, 0x20];
+        prevout.extend_from_slice(&Sha256::digest(&witness_script));
+        let prevout = Script::new(prevout);
+
+        assert_eq!(
+            tx.p2wsh_witness_script(0),
+            Some(Script::new(witness_script))
+        );
+        assert!(tx.p2wsh_matches(0, &prevout));
+
+        let mut other = prevout.clone();
+        other.bytes[2] ^= 0x01;
+        assert!(!tx.p2wsh_matches(0, &other));
+        assert!(!tx.p2wsh_matches(0, &Script::p2wpkh(&[0x22; 20])));
+        assert!(!tx.p2wsh_matches(1, &prevout));
+        assert_eq!(tx.p2wsh_witness_script(1), None);
+    }
+
+    #[test]
+    fn test_per_input_weight() {
+        let input = |vout| {
+            TransactionInput::new(
+                OutPoint::new(dummy_txid(0x11), vout),
+                Script::new(Vec::new()),
+                0xFFFFFFFF,
+            )
+        };
+        let output = TransactionOutput::new(50_000, Script::p2wpkh(&[0x22; 20]));
+        let mut tx = BitcoinTransaction::new(2, vec![input(0), input(1)], vec![output], 0);
+
+        // P2WPKH: signature and pubkey. 2-of-3 P2WSH multisig: dummy, two
+        // signatures and a 105-byte witness script
+        let mut witness_script = vec![0x52];
+        for key in [0x02, 0x03, 0x02] {
+            witness_script.push(0x21);
+            witness_script.extend_from_slice(&[key; 33]);
+        }
+        witness_script.extend_from_slice(&[0x53, 0xAE]);
+        tx.witnesses = vec![
+            vec![vec![0x30; 72], vec![0x02; 33]],
+            vec![Vec::new(), vec![0x30; 72], vec![0x30; 72], witness_script],
+        ];
+
+        let weights = tx.per_input_weight();
+        assert_eq!(
+            weights,
+            vec![41 * 4 + (1 + 73 + 34), 41 * 4 + (1 + 1 + 73 + 73 + 106)]
+        );
+        assert!(weights[1] > weights[0]);
+
+        // The rest of the weight is the shared overhead
+        let shared = tx.weight() - weights.iter().sum::<usize>();
+        assert_eq!(shared, (4 + 1 + 1 + 31 + 4) * 4 + 2);
+
+        tx.witnesses.clear();
+        assert_eq!(tx.per_input_weight(), vec![41 * 4, 41 * 4]);
+    }
+
+    #[test]
+    fn test_verify_size_consistency() {
+        let legacy = BitcoinTransaction::from_bytes(&hex::decode(BLOCK_170_TX).unwrap())
+            .unwrap()
+            .0;
+        let segwit = BitcoinTransaction::from_bytes(&hex::decode(BIP143_P2WPKH_TX).unwrap())
+            .unwrap()
+            .0;
+        let empty = BitcoinTransaction::new(1, vec![], vec![], 0);
+        // Script lengths needing 3- and 5-byte CompactSize prefixes
+        let large = legacy.with_dummy_scripts(300, 70_000);
+
+        for tx in [legacy, segwit, empty, large] {
+            assert!(tx.verify_size_consistency());
+        }
+    }
 }